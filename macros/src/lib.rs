@@ -0,0 +1,95 @@
+//! Proc-macro front end of ytbgen
+//!
+//! ```ignore
+//! ytbgen_macros::include_patterns!("src/x86/patterns.td");
+//! ytbgen_macros::include_patterns!("src/aarch64/patterns.td", aarch64);
+//! ```
+//!
+//! expands to the `compile`, `tmps` and `overwrittes` functions at compile time.
+
+use std::path::Path;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse::{Parse, ParseStream}, Ident, LitStr, Token};
+use ytbgen::{AstTarget, Builder};
+
+/// The path of the .td file and the optional target (x86 by default)
+struct Input {
+    path: LitStr,
+    target: AstTarget,
+}
+
+impl Parse for Input {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path = input.parse()?;
+        let mut target = AstTarget::X86;
+
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let ident: Ident = input.parse()?;
+            target = ident.to_string().parse().map_err(|err: String| syn::Error::new(ident.span(), err))?;
+            input.parse::<Option<Token![,]>>()?;
+        }
+
+        Ok(Self { path, target })
+    }
+}
+
+/// Generates the instruction selection of a .td file
+///
+/// The path is relative to the root of the crate invoking the macro, it can be
+/// followed by the target (`x86`, `aarch64` or `riscv64`). Errors in the .td file are
+/// reported at the path literal with their `file:line:col`, since spans cannot
+/// point outside of rust sources on stable.
+#[proc_macro]
+pub fn include_patterns(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as Input);
+
+    match expand(&input.path, input.target) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(path: &LitStr, target: AstTarget) -> syn::Result<proc_macro2::TokenStream> {
+    let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let file = Path::new(&root).join(path.value());
+
+    // the generator relies on span locations to map errors back to the .td file,
+    // which only the fallback implementation provides on stable
+    let generated = {
+        let _fallback = ForcedFallback::new();
+        Builder::new().input(file).target(target).generate_code()
+    };
+
+    let generated = generated.map_err(|err| syn::Error::new(path.span(), err))?;
+
+    let code: proc_macro2::TokenStream = generated.code.parse()?;
+    let sources = generated.sources.iter().map(|source| source.display().to_string());
+
+    Ok(quote! {
+        // makes cargo rebuild the crate when one of the .td files changes
+        #(const _: &[u8] = include_bytes!(#sources);)*
+
+        #code
+    })
+}
+
+/// Forces the fallback implementation of proc_macro2 until it is dropped
+///
+/// The flag is global to the compiler process, so it is reset even if the
+/// generator panics, otherwise every later macro would use the fallback
+struct ForcedFallback;
+
+impl ForcedFallback {
+    fn new() -> Self {
+        proc_macro2::fallback::force();
+        Self
+    }
+}
+
+impl Drop for ForcedFallback {
+    fn drop(&mut self) {
+        proc_macro2::fallback::unforce();
+    }
+}
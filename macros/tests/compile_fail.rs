@@ -0,0 +1,5 @@
+#[test]
+fn errors_point_into_the_td_file() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,4 @@
+// trybuild builds this file from target/tests/trybuild/ytbgen-macros
+ytbgen_macros::include_patterns!("../../../../macros/tests/ui/bad.td");
+
+fn main() {}
@@ -1,32 +1,307 @@
-use std::{fmt::Display, str::FromStr};
+use std::{fmt::Display, path::{Path, PathBuf}, str::FromStr};
 use pest::Parser;
 use pest_derive::Parser;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+use crate::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct File {
     pub patterns: Vec<Pattern>,
+    pub insts: Vec<Inst>,
+    /// name of the generated assembly text parser (`asm_parser = parse_asm;`)
     pub asm_parser: Option<String>,
+    pub config: Config,
+    /// every file read while parsing (the main file and its includes) with its content
+    pub sources: Vec<(PathBuf, String)>,
+}
+
+/// Paths of the runtime types the generated code refers to
+/// 
+/// Set through the `config { ... }` section at the top of the .td file. The
+/// generated code also names `Operand`, `MemoryDispl`, `MemoryOption`,
+/// `DagOpCode`, `OperationHandler`, `Shift` and `Extend` without a path, so
+/// they must be in scope where it is included (e.g. with `use backend::*;`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub module: String,
+    pub node: String,
+    pub tmp_info: String,
+    pub reg: String,
+    pub asm: String,
+    /// module containing `TypeMetadata`, `VecTy` and `StdTypeMetadata`
+    pub ir: String,
+    /// visibility of the generated functions (empty for private)
+    pub visibility: String,
+    /// additional `use` imports emitted on top of the generated code
+    pub uses: Vec<String>,
+    /// called with the pattern id, its .td location and the node for every
+    /// compiled node (`log` uses `log::trace!`)
+    pub trace: Option<String>,
+    /// cargo feature of the generated code which enables tracing, the crate
+    /// including the code has to declare it or rustc warns about an unexpected
+    /// cfg (`none` traces unconditionally)
+    pub trace_feature: Option<String>,
+    /// return `Result<(), SelectError>` from `compile` instead of panicking
+    pub fallible: bool,
+    /// the scalar types `legal_types` chooses from (`types = i32, i64;`)
+    pub types: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            module: "crate::IR::Module".to_string(),
+            node: "dag::DagNode".to_string(),
+            tmp_info: "dag::DagTmpInfo".to_string(),
+            reg: "Reg".to_string(),
+            asm: "Asm".to_string(),
+            ir: "crate::IR".to_string(),
+            visibility: "pub".to_string(),
+            uses: Vec::new(),
+            trace: None,
+            trace_feature: Some("trace".to_string()),
+            fallible: false,
+            types: ["u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64", "ptr", "f32", "f64"].map(String::from).to_vec(),
+        }
+    }
+}
+
+/// An instruction of the target: `def Inst mov : (r/m64, r64), (r64, imm32);`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inst {
+    pub name: String,
+    /// the operand forms the instruction accepts (one empty form without operands)
+    pub forms: Vec<InstForm>,
+    pub file: String,
+    pub loc: Loc,
+}
+
+/// One operand form of an instruction: `(r/m64, imm32) = { REX.W 81 /0 id }`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstForm {
+    pub operands: Vec<String>,
+    pub encoding: Option<Encoding>,
+    pub loc: Loc,
+}
+
+impl InstForm {
+    /// The size of the first register or memory operand with one (`r/m64` -> 64)
+    pub fn size(&self) -> Option<u32> {
+        self.operands.iter()
+            .filter(|op| !matches!(OperandSpec::of(op), OperandSpec::Imm(_) | OperandSpec::UImm(_)))
+            .find_map(|op| op[op.trim_end_matches(|c: char| c.is_ascii_digit()).len()..].parse().ok())
+    }
+}
+
+/// How an instruction form is encoded into machine code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Encoding {
+    /// `{ REX.W 01 /r }`
+    X86(X86Encoding),
+    /// `bits { $3[11:0] $2:5 0b000:3 $1:5 0x13:7 }`, most significant field first
+    Bits(Vec<BitField>),
+}
+
+/// A byte oriented x86 encoding in the notation of the intel manual
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct X86Encoding {
+    /// legacy prefixes in front of the rex prefix (`66`, `F3`, ...)
+    pub prefixes: Vec<u8>,
+    pub rex_w: bool,
+    pub opcode: Vec<u8>,
+    /// `+r`: the register operand is added to the last opcode byte
+    pub plus_reg: bool,
+    pub modrm: Option<ModRm>,
+    /// size of the immediate in bytes (`ib`, `iw`, `id`, `io`)
+    pub imm: Option<usize>,
+}
+
+/// What goes into the reg field of the modrm byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModRm {
+    /// `/r`: the register operand
+    Reg,
+    /// `/0` - `/7`: an opcode extension
+    Digit(u8),
+}
+
+/// The bits `hi..=lo` of `value` in a fixed width encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitField {
+    pub value: BitValue,
+    pub hi: u32,
+    pub lo: u32,
+}
+
+impl BitField {
+    pub fn width(&self) -> u32 {
+        self.hi - self.lo + 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitValue {
+    Const(u64),
+    /// `$N`: the register number or immediate of operand N (starting at 0)
+    Operand(usize),
+    /// `$N.base`: the base register of memory operand N
+    Base(usize),
+    /// `$N.offset`: the displacement of memory operand N
+    Offset(usize),
+}
+
+/// What an operand of an instruction form (`r/m64`, `imm8`, ...) accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandSpec {
+    Reg,
+    Mem,
+    RegOrMem,
+    /// `immN`: an immediate of N bits, sign extended unless it is as wide as
+    /// the other operands of the form. `imm` of forms without an encoding
+    /// takes any value (`Imm(64)`)
+    Imm(u32),
+    /// `uimmN`: an unsigned immediate of N bits
+    UImm(u32),
+}
+
+impl OperandSpec {
+    /// The spec of a form operand, the width of immediates is checked by the parser
+    pub fn of(spec: &str) -> Self {
+        if let Some(width) = spec.strip_prefix("uimm") {
+            OperandSpec::UImm(width.parse().unwrap_or(64))
+        } else if let Some(width) = spec.strip_prefix("imm") {
+            OperandSpec::Imm(width.parse().unwrap_or(64))
+        } else if spec.contains("/m") {
+            OperandSpec::RegOrMem
+        } else if spec.starts_with('m') {
+            OperandSpec::Mem
+        } else {
+            OperandSpec::Reg
+        }
+    }
+
+    /// The values an immediate operand takes, None if it is no immediate or
+    /// takes every value
+    ///
+    /// `size` is the operand size of the form (see [`InstForm::size`])
+    pub fn imm_range(&self, size: Option<u32>) -> Option<(i64, i64)> {
+        let (min, max): (i128, i128) = match *self {
+            // every bit is encoded, so the unsigned values are the same
+            OperandSpec::Imm(width) if size.is_some_and(|size| size <= width) => (-(1 << (width - 1)), (1 << width) - 1),
+            OperandSpec::Imm(width) => (-(1 << (width - 1)), (1 << (width - 1)) - 1),
+            OperandSpec::UImm(64) => return None,
+            OperandSpec::UImm(width) => (0, (1 << width) - 1),
+            _ => return None,
+        };
+
+        if min <= i64::MIN as i128 && max >= i64::MAX as i128 {
+            return None;
+        }
+
+        Some((min.max(i64::MIN as i128) as i64, max.min(i64::MAX as i128) as i64))
+    }
 }
 
+const LEGACY_PREFIXES: [u8; 11] = [0x66, 0x67, 0xF0, 0xF2, 0xF3, 0x2E, 0x36, 0x3E, 0x26, 0x64, 0x65];
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Pattern {
     pub variant: Variant,
+    /// the .td file the pattern is defined in
+    pub file: String,
+    pub loc: Loc,
     pub lines: Vec<AsmLine>,
     pub maps: Vec<Map>,
     pub overwrittes: Vec<String>,
+    /// registers (or `flags`) the instructions implicitly read (`uses rax, rdx;`)
+    pub uses: Vec<String>,
+    /// registers (or `flags`) the instructions implicitly write (`defs flags;`)
+    pub defs: Vec<String>,
     pub hook: Option<String>,
+    pub cost: Cost,
+    /// also matches with the first two operands swapped (`commutative;`)
+    pub commutative: bool,
+    /// the swapped copy of a commutative pattern, `$1`/`$2` (and their
+    /// temporaries) still refer to the operands as written
+    pub mirrored: bool,
+    /// `tie $out = $1;`: the operand (1 or 2) which is copied into `$out`
+    /// before the body unless they are already equal
+    ///
+    /// The copy uses the move of the target (`mov`, and for fp operands `movss`,
+    /// `movsd` and `movaps` on x86), which must be declared if the file has `def Inst`s
+    pub tie: Option<usize>,
+}
+
+impl Pattern {
+    /// The copy of a commutative pattern which matches the swapped operands
+    ///
+    /// None if swapping the operands changes nothing
+    pub fn mirror(&self) -> Option<Pattern> {
+        let mut mirror = self.clone();
+        let variant = &mut mirror.variant;
+
+        std::mem::swap(&mut variant.ls, &mut variant.rs);
+        if variant.tree.len() >= 2 {
+            variant.tree.swap(0, 1);
+        }
+
+        mirror.mirrored = true;
+
+        if mirror.variant == self.variant { None } else { Some(mirror) }
+    }
+}
+
+/// The costs declared with `cost instrs 1, latency 1, size 3;`
+///
+/// Undeclared costs default to the number of asm lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Cost {
+    pub instrs: Option<u32>,
+    pub latency: Option<u32>,
+    pub size: Option<u32>,
+}
+
+impl Cost {
+    pub fn is_declared(&self) -> bool {
+        self.instrs.is_some() || self.latency.is_some() || self.size.is_some()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Map {
     pub var: String,
+    /// `..` for temporaries pinned to a register
     pub ty: OpVariant, 
+    /// the type of the temporary if it differs from the node (`gr:i64`)
+    pub size: Option<String>,
+    /// the physical register the temporary is pinned to (`map %t0, rcx;`)
+    pub reg: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AsmLine {
-    Rust(String),
-    Asm(String),
+    Rust(String, Loc),
+    Asm(String, Loc),
+}
+
+/// Position inside of the .td file (1-based)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Loc {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Loc {
+    fn of(pair: &pest::iterators::Pair<Rule>) -> Self {
+        let (line, col) = pair.as_span().start_pos().line_col();
+        Self { line, col }
+    }
+}
+
+impl Display for Loc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -37,6 +312,61 @@ pub struct Variant {
     pub op3: Option<OpVariant>,
     pub out: Option<OpVariant>,
     pub ty: Option<String>,
+    /// the operands of a tree pattern like `Add(gr, Mul(gr, 4|8))`, empty otherwise
+    ///
+    /// `ls`, `rs` and `op3` still describe the root operands (constants are
+    /// `imm`, nested nodes `..`)
+    pub tree: Vec<TreeOperand>,
+}
+
+/// An operand of a tree pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeOperand {
+    Kind(OpVariant),
+    /// an immediate which is one of the values (`4|8`)
+    Const(Vec<i64>),
+    /// the operand is defined by a node matching the nested pattern
+    ///
+    /// It is found through `node.get_op_node(index)`, which returns the
+    /// defining node only if it can be folded into its user
+    Node(String, Vec<TreeOperand>),
+}
+
+impl Display for TreeOperand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TreeOperand::Kind(kind) => write!(f, "{}", kind),
+            TreeOperand::Const(values) => write!(f, "{}", values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join("|")),
+            TreeOperand::Node(mnemonic, operands) => write!(f, "{}({})", mnemonic, operands.iter().map(|op| op.to_string()).collect::<Vec<_>>().join(", ")),
+        }
+    }
+}
+
+impl Display for Variant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.tree.is_empty() {
+            write!(f, "def Pat<{}", TreeOperand::Node(self.mnemonic.clone(), self.tree.clone()))?;
+        } else {
+            write!(f, "def Pat<{}", self.mnemonic)?;
+
+            let inputs = [self.ls, self.rs, self.op3].into_iter().flatten().map(|op| op.to_string()).collect::<Vec<String>>();
+            if !inputs.is_empty() {
+                write!(f, " {}", inputs.join(", "))?;
+            }
+        }
+
+        if let Some(out) = self.out {
+            write!(f, " -> {}", out)?;
+        }
+
+        write!(f, ">")?;
+
+        if let Some(ty) = &self.ty {
+            write!(f, " {}", ty)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -78,14 +408,46 @@ impl Display for OpVariant {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AstTarget {
     X86,
+    AArch64,
+    /// RV64GC
+    ///
+    /// `src/tests/riscv.td` is the example of a complete description, with
+    /// the pseudo instructions (`li`, `mv`, ...), encodings and the asm parser
+    RiscV,
+}
+
+impl FromStr for AstTarget {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "x86" | "x86_64" => Ok(AstTarget::X86),
+            "aarch64" | "arm64" => Ok(AstTarget::AArch64),
+            "riscv64" | "riscv" | "rv64" => Ok(AstTarget::RiscV),
+            _ => Err(format!("unknown target: {} (expected x86, aarch64 or riscv64)", input)),
+        }
+    }
 }
+
+impl Display for AstTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            AstTarget::X86 => "x86",
+            AstTarget::AArch64 => "aarch64",
+            AstTarget::RiscV => "riscv64",
+        })
+    }
+}
+
 #[derive(Parser)]
 #[grammar = "grammar.pest"]
 pub struct CodeParser;
 
-pub fn process(pair: pest::iterators::Pair<Rule>) -> Pattern {
+pub fn process(pair: pest::iterators::Pair<Rule>) -> Result<Pattern, Error> {
     match pair.as_rule() {
         Rule::pattern => {
+            let loc = Loc::of(&pair);
+
             let mut pattern = Pattern {
                 variant: Variant { 
                     mnemonic: String::new(), 
@@ -94,31 +456,63 @@ pub fn process(pair: pest::iterators::Pair<Rule>) -> Pattern {
                     op3: None, 
                     out: None,
                     ty: None,
+                    tree: Vec::new(),
                 },
+                file: String::new(),
+                loc,
                 maps: Vec::new(),
                 overwrittes: Vec::new(),
+                uses: Vec::new(),
+                defs: Vec::new(),
                 lines: Vec::new(),
                 hook: None,
+                cost: Cost::default(),
+                commutative: false,
+                mirrored: false,
+                tie: None,
             };
             
             for inner_pair in pair.into_inner() {
+                let inner_loc = Some(Loc::of(&inner_pair));
+
                 match inner_pair.as_rule() {
                     Rule::mnemonic => pattern.variant.mnemonic = inner_pair.as_str().to_string(),
+                    Rule::tree => {
+                        let TreeOperand::Node(mnemonic, operands) = process_tree(inner_pair)? else {
+                            unreachable!("a tree is always a node")
+                        };
+
+                        if operands.len() > 3 {
+                            return Err(Error::new(inner_loc, format!("{} takes at most 3 operands", mnemonic)));
+                        }
+
+                        let kinds = operands.iter().map(|op| Some(match op {
+                            TreeOperand::Kind(kind) => *kind,
+                            TreeOperand::Const(_) => OpVariant::Imm,
+                            TreeOperand::Node(..) => OpVariant::Any,
+                        })).collect::<Vec<_>>();
+
+                        pattern.variant.mnemonic = mnemonic;
+                        pattern.variant.ls = kinds.first().copied().flatten();
+                        pattern.variant.rs = kinds.get(1).copied().flatten();
+                        pattern.variant.op3 = kinds.get(2).copied().flatten();
+                        pattern.variant.tree = operands;
+                    },
                     Rule::inputs => {
                         if inner_pair.as_str().is_empty() { continue; }
 
                         let inputs = inner_pair.as_str().split(", ").map(|x| x.to_owned()).collect::<Vec<String>>();
 
-                        if let Some(input) = inputs.get(0) {
-                            pattern.variant.ls = Some(OpVariant::from_str(input).expect(&format!("invalid opvariant for ls: {}", input)))
+                        if let Some(input) = inputs.first() {
+                            pattern.variant.ls = Some(OpVariant::from_str(input).map_err(|_| Error::new(inner_loc, format!("invalid opvariant for ls: {}", input)))?)
                         }
 
                         if let Some(input) = inputs.get(1) {
-                            pattern.variant.rs = Some(OpVariant::from_str(input).expect(&format!("invalid opvariant for rs: {}", input)))
+                            pattern.variant.rs = Some(OpVariant::from_str(input).map_err(|_| Error::new(inner_loc, format!("invalid opvariant for rs: {}", input)))?)
                         }
 
                         if let Some(input) = inputs.get(2) {
-                            pattern.variant.op3 = Some(OpVariant::from_str(input).expect(&format!("invalid opvariant for op3: {}", input)))
+                            pattern.variant.op3 = Some(OpVariant::from_str(input).map_err(|_| Error::new(inner_loc, format!("invalid opvariant for op3: {}", input)))?)
                         }
                     },
                     Rule::optional_ty => {
@@ -131,24 +525,31 @@ pub fn process(pair: pest::iterators::Pair<Rule>) -> Pattern {
                         if inner_pair.as_str().is_empty() { continue; }
                         
                         let input = inner_pair.as_str().replace("->", "").replace(" ", "").replace(";", "");
-                        let out = OpVariant::from_str(&input).expect(&format!("invalid opvariant for out: {}", input));
+                        let out = OpVariant::from_str(&input).map_err(|_| Error::new(inner_loc, format!("invalid opvariant for out: {}", input)))?;
 
                         pattern.variant.out = Some(out);
                     },
                     Rule::block => process_block(&mut pattern, inner_pair),
                     Rule::map => {
-                        let map = inner_pair.into_inner().as_str();
-                        let map = map.replace(" ", "").replace(";", "");
-
-                        let map_parts = map.split(",").collect::<Vec<&str>>();
-                        let tmp_name = map_parts[0];
-                        let tmp_ty = OpVariant::from_str(map_parts[1]).expect("expected valid opvariant");
+                        let mut inner = inner_pair.into_inner();
+                        let tmp_name = inner.next().expect("expected temporary").as_str();
 
-                        let map = Map {
+                        let mut map = Map {
                             var: tmp_name.to_string(),
-                            ty: tmp_ty,
+                            ty: OpVariant::Any,
+                            size: None,
+                            reg: None,
                         };
 
+                        for part in inner {
+                            match part.as_rule() {
+                                Rule::input => map.ty = OpVariant::from_str(part.as_str()).map_err(|_| Error::new(inner_loc, format!("invalid opvariant for map: {}", part.as_str())))?,
+                                Rule::map_ty => map.size = Some(part.as_str().to_string()),
+                                Rule::map_reg => map.reg = Some(part.as_str().to_string()),
+                                _ => {},
+                            }
+                        }
+
                         pattern.maps.push( map );
                     },
                     Rule::hook => {
@@ -157,11 +558,57 @@ pub fn process(pair: pest::iterators::Pair<Rule>) -> Pattern {
                         let hook = hook.replace(" ", "").replace(";", "");
 
                         if pattern.hook.is_some() {
-                            panic!("currently you can only have one hook");
+                            return Err(Error::new(inner_loc, "currently you can only have one hook"));
                         }
 
                         pattern.hook = Some(hook);
                     }
+                    Rule::uses => pattern.uses.extend(inner_pair.into_inner().map(|reg| reg.as_str().to_string())),
+                    Rule::defs => pattern.defs.extend(inner_pair.into_inner().map(|reg| reg.as_str().to_string())),
+                    Rule::tie => {
+                        let var = inner_pair.into_inner().as_str();
+
+                        let tie = match var {
+                            "$1" => 1,
+                            "$2" => 2,
+                            _ => return Err(Error::new(inner_loc, format!("`$out` can only be tied to `$1` or `$2`, not `{}`", var))),
+                        };
+
+                        if pattern.tie.is_some() {
+                            return Err(Error::new(inner_loc, "`$out` is already tied"));
+                        }
+
+                        pattern.tie = Some(tie);
+                    },
+                    Rule::commutative => {
+                        if pattern.variant.ls.is_none() || pattern.variant.rs.is_none() {
+                            return Err(Error::new(inner_loc, "commutative patterns need at least two operands"));
+                        }
+
+                        pattern.commutative = true;
+                    },
+                    Rule::cost => {
+                        for entry in inner_pair.into_inner() {
+                            let entry_loc = Some(Loc::of(&entry));
+                            let mut entry = entry.into_inner();
+                            let goal = entry.next().expect("expected cost goal").as_str();
+                            let value = entry.next().expect("expected cost value").as_str();
+
+                            let value = value.parse::<u32>().map_err(|_| Error::new(entry_loc, format!("invalid cost: {}", value)))?;
+
+                            let cost = match goal {
+                                "instrs" => &mut pattern.cost.instrs,
+                                "latency" => &mut pattern.cost.latency,
+                                _ => &mut pattern.cost.size,
+                            };
+
+                            if cost.is_some() {
+                                return Err(Error::new(entry_loc, format!("the {} cost is declared twice", goal)));
+                            }
+
+                            *cost = Some(value);
+                        }
+                    },
                     Rule::overwrite => {
                         let overwrite = inner_pair.as_str();
                         if overwrite.is_empty() { continue; }
@@ -175,25 +622,308 @@ pub fn process(pair: pest::iterators::Pair<Rule>) -> Pattern {
                 }
             }
 
-            pattern
+            Ok(pattern)
         }
         unhandled => todo!("{:?}", unhandled),
     }
 }
 
+pub fn process_config(file: &mut File, pair: pest::iterators::Pair<Rule>) -> Result<(), Error> {
+    for inner_pair in pair.into_inner() {
+        match inner_pair.as_rule() {
+            Rule::config_use => {
+                let path = inner_pair.into_inner().as_str().trim().to_string();
+                file.config.uses.push(path);
+            },
+            Rule::config_option => {
+                let loc = Some(Loc::of(&inner_pair));
+                let mut inner = inner_pair.into_inner();
+                let key = inner.next().expect("expected config key").as_str();
+                let value = inner.next().expect("expected config value").as_str().trim().to_string();
+
+                match key {
+                    "module" => file.config.module = value,
+                    "node" => file.config.node = value,
+                    "tmp_info" => file.config.tmp_info = value,
+                    "reg" => file.config.reg = value,
+                    "asm" => file.config.asm = value,
+                    "ir" => file.config.ir = value,
+                    "asm_parser" => file.asm_parser = Some(value),
+                    "visibility" => file.config.visibility = if value == "private" { String::new() } else { value },
+                    "trace" => file.config.trace = Some(value),
+                    "trace_feature" => file.config.trace_feature = if value == "none" { None } else { Some(value) },
+                    "types" => file.config.types = value.split(',').map(|ty| ty.trim().to_string()).filter(|ty| !ty.is_empty()).collect(),
+                    "fallible" => file.config.fallible = match value.as_str() {
+                        "true" => true,
+                        "false" => false,
+                        _ => return Err(Error::new(loc, format!("expected `true` or `false` for `fallible`, found: {}", value))),
+                    },
+                    unknown => return Err(Error::new(loc, format!("unknown config option: {}", unknown))),
+                }
+            },
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn process_tree(pair: pest::iterators::Pair<Rule>) -> Result<TreeOperand, Error> {
+    let mut inner = pair.into_inner();
+    let mnemonic = inner.next().expect("expected tree mnemonic").as_str().to_string();
+
+    let mut operands = Vec::new();
+
+    for operand in inner {
+        let loc = Some(Loc::of(&operand));
+        let operand = operand.into_inner().next().expect("expected tree operand");
+
+        operands.push(match operand.as_rule() {
+            Rule::tree => process_tree(operand)?,
+            Rule::input => TreeOperand::Kind(OpVariant::from_str(operand.as_str()).map_err(|_| Error::new(loc, format!("invalid opvariant: {}", operand.as_str())))?),
+            _ => {
+                let values = operand.into_inner()
+                    .map(|value| value.as_str().parse::<i64>().map_err(|_| Error::new(loc, format!("invalid constant: {}", value.as_str()))))
+                    .collect::<Result<_, _>>()?;
+
+                TreeOperand::Const(values)
+            },
+        });
+    }
+
+    Ok(TreeOperand::Node(mnemonic, operands))
+}
+
+fn process_inst_form(pair: pest::iterators::Pair<Rule>) -> Result<InstForm, Error> {
+    let loc = Loc::of(&pair);
+    let mut operands = Vec::new();
+    let mut encoding = None;
+    // immediates without a width and where they are
+    let mut unsized_imms = Vec::new();
+
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::inst_operand => {
+                let op = inner.as_str();
+
+                match op.strip_prefix("uimm").or_else(|| op.strip_prefix("imm")) {
+                    // takes any value
+                    Some("") => unsized_imms.push((op.to_string(), Loc::of(&inner))),
+                    Some(width) if !width.parse::<u32>().is_ok_and(|width| (1..=64).contains(&width)) => {
+                        return Err(Error::new(Some(Loc::of(&inner)), format!("`{}` needs a width of 1 to 64 bits (`imm32`, `uimm6`, ...)", op)));
+                    },
+                    _ => {},
+                }
+
+                operands.push(op.to_string())
+            },
+            _ => {
+                if let Some((op, op_loc)) = unsized_imms.first() {
+                    return Err(Error::new(Some(*op_loc), format!("`{}` takes any value, which encoded forms can't, give it a width (`{}32`, ...)", op, op)));
+                }
+
+                let enc_loc = Some(Loc::of(&inner));
+                let enc = inner.into_inner().next().expect("expected encoding");
+
+                encoding = Some(match enc.as_rule() {
+                    Rule::x86_encoding => Encoding::X86(process_x86_encoding(enc, &operands)?),
+                    _ => {
+                        let fields = enc.into_inner().map(|field| process_bit_field(field, operands.len())).collect::<Result<Vec<_>, _>>()?;
+                        let width: u32 = fields.iter().map(BitField::width).sum();
+
+                        if width == 0 || width % 8 != 0 || width > 64 {
+                            return Err(Error::new(enc_loc, format!("the encoding is {} bits wide, expected a multiple of 8 up to 64", width)));
+                        }
+
+                        Encoding::Bits(fields)
+                    },
+                });
+            },
+        }
+    }
+
+    Ok(InstForm { operands, encoding, loc })
+}
+
+fn process_x86_encoding(pair: pest::iterators::Pair<Rule>, operands: &[String]) -> Result<X86Encoding, Error> {
+    let loc = Some(Loc::of(&pair));
+    let mut enc = X86Encoding::default();
+
+    for item in pair.into_inner() {
+        let item_loc = Some(Loc::of(&item));
+
+        match item.as_str() {
+            "REX.W" => enc.rex_w = true,
+            "/r" => enc.modrm = Some(ModRm::Reg),
+            "+r" => enc.plus_reg = true,
+            "ib" => enc.imm = Some(1),
+            "iw" => enc.imm = Some(2),
+            "id" => enc.imm = Some(4),
+            "io" => enc.imm = Some(8),
+            digit if digit.starts_with('/') => {
+                let digit = digit[1..].parse::<u8>().ok().filter(|digit| *digit < 8).ok_or_else(|| Error::new(item_loc, format!("invalid opcode extension: {}", digit)))?;
+                enc.modrm = Some(ModRm::Digit(digit));
+            },
+            byte => {
+                let byte = u8::from_str_radix(byte, 16).map_err(|_| Error::new(item_loc, format!("invalid opcode byte: {}", byte)))?;
+
+                if enc.opcode.is_empty() && !enc.rex_w && LEGACY_PREFIXES.contains(&byte) {
+                    enc.prefixes.push(byte);
+                } else {
+                    enc.opcode.push(byte);
+                }
+            },
+        }
+    }
+
+    if enc.opcode.is_empty() {
+        return Err(Error::new(loc, "the encoding has no opcode byte"));
+    }
+
+    let kinds: Vec<OperandSpec> = operands.iter().map(|op| OperandSpec::of(op)).collect();
+
+    if (enc.plus_reg || enc.modrm == Some(ModRm::Reg)) && !kinds.contains(&OperandSpec::Reg) {
+        return Err(Error::new(loc, "the encoding needs a register operand (`r64`, ...)"));
+    }
+
+    if enc.modrm.is_some() && !kinds.iter().any(|kind| matches!(kind, OperandSpec::RegOrMem | OperandSpec::Mem)) {
+        return Err(Error::new(loc, "the encoding needs a modrm operand (`r/m64`, `m64`, ...)"));
+    }
+
+    if let Some(size) = enc.imm {
+        let width = match kinds.iter().find(|kind| matches!(kind, OperandSpec::Imm(_) | OperandSpec::UImm(_))) {
+            Some(OperandSpec::Imm(width) | OperandSpec::UImm(width)) => *width,
+            _ => return Err(Error::new(loc, "the encoding needs an immediate operand (`imm32`, ...)")),
+        };
+
+        if width != size as u32 * 8 {
+            return Err(Error::new(loc, format!("the encoding has a {} bit immediate, but the operand is {} bits wide", size * 8, width)));
+        }
+    }
+
+    Ok(enc)
+}
+
+fn process_bit_field(pair: pest::iterators::Pair<Rule>, operands: usize) -> Result<BitField, Error> {
+    let loc = Some(Loc::of(&pair));
+    let mut inner = pair.into_inner();
+    let value = inner.next().expect("expected bit field value");
+
+    let number = |pair: pest::iterators::Pair<Rule>| pair.as_str().parse::<u32>().map_err(|_| Error::new(loc, format!("invalid bit index: {}", pair.as_str())));
+
+    let (hi, lo) = match inner.next().expect("expected bit field width") {
+        range if range.as_rule() == Rule::bits_range => {
+            let mut range = range.into_inner();
+            (number(range.next().expect("expected bit"))?, number(range.next().expect("expected bit"))?)
+        },
+        width => (number(width)?.wrapping_sub(1), 0),
+    };
+
+    if hi < lo || hi >= 64 {
+        return Err(Error::new(loc, format!("invalid bit field: {}", value.as_str())));
+    }
+
+    let value = match value.as_rule() {
+        Rule::bits_operand => {
+            let (num, field) = value.as_str()[1..].split_once('.').unwrap_or((&value.as_str()[1..], ""));
+            let num = num.parse::<usize>().ok().filter(|num| *num >= 1 && *num <= operands).ok_or_else(|| Error::new(loc, format!("the form has no operand {}", value.as_str())))? - 1;
+
+            match field {
+                "base" => BitValue::Base(num),
+                "offset" => BitValue::Offset(num),
+                _ => BitValue::Operand(num),
+            }
+        },
+        _ => {
+            let text = value.as_str();
+
+            let parsed = if let Some(bin) = text.strip_prefix("0b") {
+                u64::from_str_radix(bin, 2)
+            } else if let Some(hex) = text.strip_prefix("0x") {
+                u64::from_str_radix(hex, 16)
+            } else {
+                text.parse::<u64>()
+            };
+
+            let value = parsed.map_err(|_| Error::new(loc, format!("invalid constant: {}", text)))?;
+
+            if hi - lo < 63 && value >> (hi - lo + 1) != 0 {
+                return Err(Error::new(loc, format!("the constant {} does not fit into {} bits", text, hi - lo + 1)));
+            }
+
+            BitValue::Const(value)
+        },
+    };
+
+    Ok(BitField { value, hi, lo })
+}
+
 pub fn process_block(pattern: &mut Pattern, pair: pest::iterators::Pair<Rule>) {
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
-            Rule::asm_instruction => pattern.lines.push(AsmLine::Asm(inner_pair.as_str().to_string().replace("asm ->", "").replace(";", ""))),
-            Rule::rust_instruction => pattern.lines.push(AsmLine::Rust(inner_pair.as_str().to_string().replace("rust ->", ""))),
+            Rule::asm_instruction => pattern.lines.push(AsmLine::Asm(inner_pair.as_str().to_string().replace("asm ->", "").replace(";", ""), Loc::of(&inner_pair))),
+            Rule::rust_instruction => {
+                let code = inner_pair.into_inner().next().expect("expected rust code");
+                pattern.lines.push(AsmLine::Rust(code.as_str().to_string(), Loc::of(&code)))
+            },
             _ => {}
         }
     }
 }
 
-pub fn parse(input: &str) -> Vec<Pattern> {
-    let mut patterns = Vec::new();
+/// Parses the .td file at `path` including all files it includes
+pub fn parse_file(path: impl AsRef<Path>) -> Result<File, Error> {
+    let mut file = File::default();
+
+    read_into(&mut file, path.as_ref(), 0)?;
+    check_mirrors(&file)?;
 
+    Ok(file)
+}
+
+/// Parses a .td file from a string, includes are resolved relative to the current directory
+pub fn parse(input: &str) -> Result<File, Error> {
+    let mut file = File::default();
+
+    parse_into(&mut file, input, "<input>", Path::new(""), 0)?;
+    check_mirrors(&file)?;
+
+    Ok(file)
+}
+
+/// Reports hand written patterns which are already covered by a commutative one
+fn check_mirrors(file: &File) -> Result<(), Error> {
+    for mirror in file.patterns.iter().filter(|pattern| pattern.mirrored) {
+        if let Some(duplicate) = file.patterns.iter().find(|pattern| !pattern.mirrored && pattern.variant == mirror.variant) {
+            return Err(Error {
+                file: Some(duplicate.file.clone()),
+                loc: Some(duplicate.loc),
+                pattern: Some(duplicate.variant.to_string()),
+                message: format!("duplicates the swapped variant of the commutative pattern at {}:{}", mirror.file, mirror.loc),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Maximum nesting of includes, deeper nesting is most likely an include cycle
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+fn read_into(file: &mut File, path: &Path, depth: usize) -> Result<(), Error> {
+    let name = path.display().to_string();
+
+    let input = match std::fs::read_to_string(path) {
+        Ok(input) => input.replace("\r", ""),
+        Err(err) => return Err(Error::new(None, err).in_file(name)),
+    };
+
+    file.sources.push((path.to_path_buf(), input.clone()));
+
+    parse_into(file, &input, &name, path.parent().unwrap_or(Path::new("")), depth)
+}
+
+fn parse_into(file: &mut File, input: &str, name: &str, dir: &Path, depth: usize) -> Result<(), Error> {
     match CodeParser::parse(Rule::patterns, input) {
         Ok(pairs) => {
             for pair in pairs {
@@ -201,18 +931,67 @@ pub fn parse(input: &str) -> Vec<Pattern> {
                     let inner = pair.into_inner();
 
                     for pair in inner { 
-                        if pair.as_rule() == Rule::pattern { // else it is probably eoi
-                            patterns.push( process(pair) );
+                        match pair.as_rule() {
+                            Rule::pattern => {
+                                let mut pattern = process(pair).map_err(|err| err.in_file(name))?;
+                                pattern.file = name.to_string();
+
+                                let mirror = if pattern.commutative { pattern.mirror() } else { None };
+
+                                file.patterns.push( pattern );
+                                file.patterns.extend(mirror);
+                            },
+                            Rule::inst => {
+                                let loc = Loc::of(&pair);
+                                let mut inner = pair.into_inner();
+                                let inst_name = inner.next().expect("expected instruction name").as_str().to_string();
+
+                                if let Some(existing) = file.insts.iter().find(|inst| inst.name == inst_name) {
+                                    return Err(Error::new(Some(loc), format!("instruction `{}` is already defined at {}:{}", inst_name, existing.file, existing.loc)).in_file(name));
+                                }
+
+                                let mut forms = inner.map(process_inst_form).collect::<Result<Vec<_>, _>>().map_err(|err| err.in_file(name))?;
+
+                                // `def Inst ret;` takes no operands
+                                if forms.is_empty() {
+                                    forms.push(InstForm { operands: Vec::new(), encoding: None, loc });
+                                }
+
+                                file.insts.push(Inst { name: inst_name, forms, file: name.to_string(), loc });
+                            },
+                            Rule::config => {
+                                if depth > 0 {
+                                    return Err(Error::new(Some(Loc::of(&pair)), "config sections are only allowed in the main file").in_file(name));
+                                }
+
+                                process_config(file, pair).map_err(|err| err.in_file(name))?
+                            },
+                            Rule::include => {
+                                let loc = Some(Loc::of(&pair));
+
+                                if depth >= MAX_INCLUDE_DEPTH {
+                                    return Err(Error::new(loc, "includes are nested too deep (include cycle?)").in_file(name));
+                                }
+
+                                let path = pair.into_inner().as_str().trim_matches('"');
+
+                                read_into(file, &dir.join(path), depth + 1)?;
+                            },
+                            _ => {} // probably eoi
                         }
                     }
                 }
             }
         }
         Err(e) => {
-            eprintln!("{}", e);
-            std::process::exit(-1);
+            let (line, col) = match e.line_col {
+                pest::error::LineColLocation::Pos(pos) => pos,
+                pest::error::LineColLocation::Span(start, _) => start,
+            };
+
+            return Err(Error::new(Some(Loc { line, col }), e.variant.message()).in_file(name));
         },
     }
 
-    patterns
-}
\ No newline at end of file
+    Ok(())
+}
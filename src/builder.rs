@@ -0,0 +1,145 @@
+use std::path::{Path, PathBuf};
+
+use crate::{ast::{self, AstTarget}, gen, Error};
+
+/// Generates the instruction selection code for a .td file
+///
+/// Meant to be used from a build script:
+///
+/// ```no_run
+/// let out_dir = std::env::var("OUT_DIR").unwrap();
+///
+/// ytbgen::Builder::new()
+///     .input("src/x86/patterns.td")
+///     .target(ytbgen::AstTarget::X86)
+///     .out_file(std::path::Path::new(&out_dir).join("x86.rs"))
+///     .generate()?;
+/// # Ok::<(), ytbgen::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builder {
+    input: Option<PathBuf>,
+    target: AstTarget,
+    out_file: Option<PathBuf>,
+    source_map: Option<PathBuf>,
+    rerun_if_changed: bool,
+}
+
+/// The generated code together with the .td files it was generated from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Generated {
+    pub code: String,
+    pub sources: Vec<PathBuf>,
+}
+
+impl Generated {
+    /// JSON source map from line ranges of the generated code to the patterns
+    /// they were generated from
+    pub fn source_map(&self) -> String {
+        gen::source_map(&self.code)
+    }
+
+    /// Whether `existing` is this code, line endings and trailing whitespace are ignored
+    pub fn is_current(&self, existing: &str) -> bool {
+        existing.replace('\r', "").trim_end() == self.code.trim_end()
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self {
+            input: None,
+            target: AstTarget::X86,
+            out_file: None,
+            source_map: None,
+            rerun_if_changed: true,
+        }
+    }
+
+    /// The .td file to generate the code from
+    pub fn input(mut self, path: impl AsRef<Path>) -> Self {
+        self.input = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn target(mut self, target: AstTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// The file the generated code is written to by [`Builder::generate`]
+    pub fn out_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.out_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Additionally writes a JSON source map (see [`Generated::source_map`])
+    /// to the given file in [`Builder::generate`]
+    pub fn source_map(mut self, path: impl AsRef<Path>) -> Self {
+        self.source_map = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Whether [`Builder::generate`] prints `cargo:rerun-if-changed` for every
+    /// read .td file (enabled by default)
+    pub fn rerun_if_changed(mut self, emit: bool) -> Self {
+        self.rerun_if_changed = emit;
+        self
+    }
+
+    /// Generates the code without writing it anywhere
+    pub fn generate_code(&self) -> Result<Generated, Error> {
+        let Some(input) = &self.input else {
+            return Err(Error::new(None, "no input file given"));
+        };
+
+        let file = ast::parse_file(input)?;
+
+        let sources = file.sources.iter().map(|(path, _)| path.to_owned()).collect();
+        let content = file.sources.iter().map(|(_, content)| content.as_str()).collect::<String>();
+
+        let emiter = gen::CodeEmitter {
+            patterns: file.patterns,
+            insts: file.insts,
+            asm_parser: file.asm_parser,
+            config: file.config,
+        };
+
+        let code = format!("{}{}", gen::header(&content), emiter.gen(self.target)?);
+
+        Ok(Generated { code, sources })
+    }
+
+    /// Generates the code and writes it to the output file
+    pub fn generate(self) -> Result<(), Error> {
+        let Some(out_file) = &self.out_file else {
+            return Err(Error::new(None, "no output file given"));
+        };
+
+        let generated = self.generate_code()?;
+
+        if self.rerun_if_changed {
+            for source in &generated.sources {
+                println!("cargo:rerun-if-changed={}", source.display());
+            }
+        }
+
+        if let Some(source_map) = &self.source_map {
+            if let Err(err) = std::fs::write(source_map, generated.source_map()) {
+                return Err(Error::new(None, err).in_file(source_map.display()));
+            }
+        }
+
+        if let Err(err) = std::fs::write(out_file, generated.code) {
+            return Err(Error::new(None, err).in_file(out_file.display()));
+        }
+
+        Ok(())
+    }
+}
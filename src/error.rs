@@ -0,0 +1,61 @@
+use std::fmt::Display;
+
+use crate::ast;
+
+/// An error in the .td file or in the rust code generated from it
+///
+/// If the error could be traced back to a pattern, it carries the pattern
+/// and the position inside of the .td file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub file: Option<String>,
+    pub loc: Option<ast::Loc>,
+    pub pattern: Option<String>,
+    pub message: String,
+}
+
+impl Error {
+    pub(crate) fn new(loc: Option<ast::Loc>, message: impl ToString) -> Self {
+        Self {
+            file: None,
+            loc,
+            pattern: None,
+            message: message.to_string(),
+        }
+    }
+
+    /// Sets the file the error occured in, if it isn't known yet
+    pub(crate) fn in_file(mut self, file: impl ToString) -> Self {
+        if self.file.is_none() {
+            self.file = Some(file.to_string());
+        }
+
+        self
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+
+        if let Some(loc) = &self.loc {
+            write!(f, "{}:", loc)?;
+        }
+
+        if self.file.is_some() || self.loc.is_some() {
+            write!(f, " ")?;
+        }
+
+        write!(f, "{}", self.message)?;
+
+        if let Some(pattern) = &self.pattern {
+            write!(f, " (in `{}`)", pattern)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,451 +1,3503 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr};
 
-use codegen::Scope;
+use proc_macro2::{Delimiter, Group, Literal, Span, TokenStream, TokenTree};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{parse::Parser, spanned::Spanned, visit::{self, Visit}};
 
-use crate::{ast, AsmLine};
+use crate::{ast::{self, AsmLine}, Error};
 
 pub struct CodeEmitter {
     pub patterns: Vec<ast::Pattern>,
+    /// the `def Inst` declarations, the `Mnemonic` enum is only generated if there are any
+    pub insts: Vec<ast::Inst>,
+    /// name of the generated assembly text parser, none is generated if it is None
+    pub asm_parser: Option<String>,
+    pub config: ast::Config,
+}
+
+/// The configured runtime paths, parsed into tokens
+struct Paths {
+    module: syn::Path,
+    node: syn::Path,
+    tmp_info: syn::Path,
+    reg: syn::Path,
+    asm: syn::Path,
+    ir: syn::Path,
+    vis: syn::Visibility,
+    uses: Vec<syn::UseTree>,
+    trace: Option<Trace>,
+    trace_feature: Option<String>,
+    fallible: bool,
+    /// the declared instructions with the operands of their forms, asm lines
+    /// are checked against them unless it is empty
+    insts: Vec<(String, Vec<Vec<ast::OperandSpec>>)>,
+}
+
+/// How matched patterns are traced
+enum Trace {
+    Log,
+    Hook(syn::Path),
+}
+
+impl Paths {
+    fn new(config: &ast::Config, insts: &[ast::Inst]) -> Result<Self, Error> {
+        Ok(Self {
+            module: parse_config("module", &config.module)?,
+            node: parse_config("node", &config.node)?,
+            tmp_info: parse_config("tmp_info", &config.tmp_info)?,
+            reg: parse_config("reg", &config.reg)?,
+            asm: parse_config("asm", &config.asm)?,
+            ir: parse_config("ir", &config.ir)?,
+            vis: parse_config("visibility", &config.visibility)?,
+            uses: config.uses.iter().map(|path| parse_config("use", path)).collect::<Result<_, _>>()?,
+            trace: match config.trace.as_deref() {
+                Some("log") => Some(Trace::Log),
+                Some(hook) => Some(Trace::Hook(parse_config("trace", hook)?)),
+                None => None,
+            },
+            trace_feature: config.trace_feature.clone(),
+            fallible: config.fallible,
+            insts: insts.iter().map(|inst| {
+                let forms = inst.forms.iter().map(|form| form.operands.iter().map(|op| ast::OperandSpec::of(op)).collect()).collect();
+                (variant_name(&inst.name), forms)
+            }).collect(),
+        })
+    }
+}
+
+fn parse_config<T: syn::parse::Parse>(key: &str, value: &str) -> Result<T, Error> {
+    syn::parse_str(value).map_err(|err| Error::new(None, format!("invalid config value for `{}`: {}", key, err)))
 }
 
 impl CodeEmitter {
-    pub fn gen(&self, target: ast::AstTarget) -> String {
-        let asm_vec = "&mut Vec<Asm>";
-
-        let mut scope = Scope::new();
-        
-        let general_func = scope.new_fn("compile")
-        .arg("asm", asm_vec)
-        .arg("node", "DagNode")
-        .arg("module", "&mut crate::IR::Module")
-        .line("match node.get_opcode() {");
-    
-        let mut funcs_in_match = Vec::new();
-
-        for pattern in &self.patterns {
-            if !funcs_in_match.contains(&pattern.variant.mnemonic) {
-                let compile_fn = format!("compile_{}", pattern.variant.mnemonic.replace("(_)", ""));
-                let line = format!("  DagOpCode::{} => {}(asm, node, module),", pattern.variant.mnemonic, compile_fn);
-                general_func.line(line);
-
-                funcs_in_match.push(pattern.variant.mnemonic.to_owned());
-            }
-        }
-        
-        general_func.line("  unimplemented => todo!(\"{:?}\", node),")
-            .line("}");
-
-        let mut funcs: HashMap<String, Vec<String>> = HashMap::new();
-
-        for pattern in &self.patterns {
-            let compile_fn = format!("compile_{}", pattern.variant.mnemonic.replace("(_)", ""));
-    
-            let mut lines = Vec::new();
-
-            // conds
-    
-            let mut close = 0;
-    
-            let construct_tabs = |close| {
-                let mut out = String::new();
-    
-                for _ in 0..close {
-                    out.push_str("  ");
-                }
-    
-                out
-            };
-    
-            if let Some(ls) = pattern.variant.ls {
-                if ls != ast::OpVariant::Any {
-                    lines.push(format!("{}if node.is_op_{}(0) {{", construct_tabs(close), ls));
-                    close += 1;
-                }
+    pub fn gen(&self, target: ast::AstTarget) -> Result<String, Error> {
+        let paths = Paths::new(&self.config, &self.insts)?;
+
+        let mnemonics = self.gen_mnemonics(&paths)?;
+        let Paths { module, node, asm, vis, uses, .. } = &paths;
+
+        let select = self.gen_select(&paths)?;
+
+        // kept in order of first appearance so the output is reproducible
+        let mut funcs: Vec<(syn::Ident, TokenStream, Vec<TokenStream>)> = Vec::new();
+        let mut expands_li = false;
+
+        for (id, pattern) in self.patterns.iter().enumerate() {
+            let name = format_ident!("compile_{}", pattern.variant.mnemonic.replace("(_)", ""));
+            let (arm, arm_expands_li) = self.construct_arm(target, &paths, id, pattern)?;
+            expands_li |= arm_expands_li;
+
+            if let Some((_, _, arms)) = funcs.iter_mut().find(|(func, ..)| *func == name) {
+                arms.push(arm);
+            } else {
+                funcs.push((name, opcode(pattern)?, vec![arm]));
             }
-    
-            if let Some(rs) = pattern.variant.rs {
-                if rs != ast::OpVariant::Any {
-                    lines.push(format!("{}if node.is_op_{}(1) {{", construct_tabs(close), rs));
-                    close += 1;
+        }
+
+        let names = funcs.iter().map(|(name, ..)| name);
+        let opcodes = funcs.iter().map(|(_, opcode, _)| opcode);
+
+        let (ret, unsupported_opcode, unsupported_operands) = if paths.fallible {
+            (
+                quote!(-> Result<(), SelectError>),
+                quote!(Err(SelectError::UnsupportedOpcode(node))),
+                quote!(Err(SelectError::UnsupportedOperands(node))),
+            )
+        } else {
+            (
+                TokenStream::new(),
+                quote!(todo!("{:?}", node)),
+                quote!(todo!("not yet compilable variant: {} ({})", node, node.get_ty())),
+            )
+        };
+
+        let compile_funcs = funcs.iter().map(|(name, _, arms)| quote! {
+            #vis fn #name(asm: &mut Vec<#asm>, node: #node, module: &mut #module, goal: CostGoal) #ret {
+                match select_with(&node, goal) {
+                    #(#arms)*
+                    _ => #unsupported_operands,
                 }
             }
+        });
 
-            if let Some(op3) = pattern.variant.op3 {
-                if op3 != ast::OpVariant::Any {
-                    lines.push(format!("{}if node.is_op_{}(2) {{", construct_tabs(close), op3));
-                    close += 1;
-                }
+        let select_error = if paths.fallible { gen_select_error(&paths) } else { TokenStream::new() };
+
+        // now handle the temporarys
+        let tmps = self.gen_tmps(&paths)?;
+
+        // handle the overwrites
+        let overwrittes = self.gen_overwrittes(&paths)?;
+
+        let info = self.gen_info(&paths)?;
+
+        let implicit = self.gen_implicit(&paths);
+
+        let legality = self.gen_legality(&paths)?;
+
+        let encode = self.gen_encode(&paths)?;
+
+        let decode = self.gen_decode(&paths);
+
+        let asm_parser = self.gen_asm_parser(target, &paths)?;
+
+        let expand_li = if expands_li { gen_expand_li(&paths) } else { TokenStream::new() };
+
+        let printer = match target {
+            ast::AstTarget::X86 => self.gen_x86_printer(&paths),
+            ast::AstTarget::AArch64 | ast::AstTarget::RiscV => TokenStream::new(),
+        };
+
+        let code = quote! {
+            #(use #uses;)*
+
+            #mnemonics
+
+            /// Index of a pattern into `PATTERNS`
+            ///
+            /// Patterns are in the order of the .td file, except that the swapped copy
+            /// of a `commutative` pattern directly follows it
+            #vis type PatternId = usize;
+
+            #info
+
+            #select
+
+            #legality
+
+            #select_error
+
+            /// Compiles `node` with the pattern [`select`] chooses
+            #vis fn compile(asm: &mut Vec<#asm>, node: #node, module: &mut #module) #ret {
+                compile_with(asm, node, module, CostGoal::default())
             }
 
-            if let Some(out) = pattern.variant.out {
-                if out != ast::OpVariant::Any {
-                    lines.push(format!("{} if node.is_out_{out}() {{", construct_tabs(close)));
-                    close += 1;
+            /// Compiles `node` with the patterns which are the cheapest for `goal`
+            #[allow(warnings)]
+            #vis fn compile_with(asm: &mut Vec<#asm>, node: #node, module: &mut #module, goal: CostGoal) #ret {
+                match node.get_opcode() {
+                    #(DagOpCode::#opcodes => #names(asm, node, module, goal),)*
+                    unimplemented => #unsupported_opcode,
                 }
             }
 
-            if let Some(ty) = &pattern.variant.ty {
-                if ty.contains("<") && ty.contains(">") {
-                    // vector type
+            #(#compile_funcs)*
+
+            #tmps
+
+            #overwrittes
+
+            #implicit
+
+            #encode
+
+            #decode
+
+            #asm_parser
+
+            #printer
 
-                    let ty = ty.replace(">", "").replace("<", "");
+            #expand_li
+        };
 
-                    let ty = ty.split("x").collect::<Vec<&str>>();
+        let file = match syn::parse2::<syn::File>(code) {
+            Ok(file) => file,
+            Err(err) => return Err(Error::new(None, format!("generated code is invalid: {}", err))),
+        };
 
-                    let size = ty.get(0).expect("expected size for vector types");
-                    let ty = ty.get(1).expect("expected type");
+        Ok(markers(&prettyplease::unparse(&file)))
+    }
+
+    /// Generates `select`, which decides which pattern compiles a node
+    ///
+    /// `compile_*`, `tmps` and `overwrittes` all dispatch on its result, so
+    /// they always agree on the pattern as long as they are given the same goal
+    fn gen_select(&self, paths: &Paths) -> Result<TokenStream, Error> {
+        let Paths { node, vis, .. } = paths;
+
+        // grouped by opcode in order of first appearance
+        let mut opcodes: Vec<(&str, TokenStream, Vec<usize>)> = Vec::new();
+
+        for (id, pat) in self.patterns.iter().enumerate() {
+            if let Some((_, _, ids)) = opcodes.iter_mut().find(|(mnemonic, ..)| *mnemonic == pat.variant.mnemonic) {
+                ids.push(id);
+            } else {
+                opcodes.push((&pat.variant.mnemonic, opcode(pat)?, vec![id]));
+            }
+        }
 
-                    lines.push(format!("{}if node.is_ty(crate::IR::TypeMetadata::Vector(crate::IR::VecTy {{ size: {size}, ty: crate::IR::StdTypeMetadata::{ty}}})) {{", construct_tabs(close)));
+        let mut arms = Vec::new();
+
+        for (_, opcode, ids) in &opcodes {
+            // the first match wins unless the patterns of the opcode declare costs
+            let by_cost = ids.iter().any(|id| self.patterns[*id].cost.is_declared());
+
+            let mut checks = Vec::new();
+
+            for id in ids {
+                let pat = &self.patterns[*id];
+                let cond = all(&self.conditions(paths, pat)?);
+                let marker = marker(pat);
+                let id = Literal::usize_unsuffixed(*id);
+
+                checks.push(if by_cost {
+                    quote! {
+                        #marker
+                        if #cond {
+                            let cost = PATTERNS[#id].cost.get(goal);
+                            if best.map_or(true, |(_, best)| cost < best) {
+                                best = Some((#id, cost));
+                            }
+                        }
+                    }
                 } else {
-                    match ty.as_str() {
-                        "int" => lines.push(format!("{}if node.get_ty().intenger() {{", construct_tabs(close))),
-                        "signed" => lines.push(format!("{}if node.get_ty().signed() {{", construct_tabs(close))),
-                        "unsigned" => lines.push(format!("{}if !node.get_ty().signed() {{", construct_tabs(close))),
-                        "float" => lines.push(format!("{}if node.get_ty().float() {{", construct_tabs(close))),
-                        "no_float" => lines.push(format!("{}if !node.get_ty().float() {{", construct_tabs(close))),
-                        _ => lines.push(format!("{}if node.is_ty(crate::IR::TypeMetadata::{}) {{", construct_tabs(close), ty)),
+                    quote!(#marker if #cond { return Some(#id); })
+                });
+            }
+
+            arms.push(if by_cost {
+                quote! {
+                    DagOpCode::#opcode => {
+                        let mut best: Option<(PatternId, u32)> = None;
+                        #(#checks)*
+                        best.map(|(id, _)| id)
                     }
                 }
-                close += 1; 
-            }
+            } else {
+                quote! {
+                    DagOpCode::#opcode => {
+                        #(#checks)*
+                        None
+                    }
+                }
+            });
+        }
 
-            construct_asm(target, &pattern, &mut lines, close, construct_tabs);
-    
-            if let Some(hook) = &pattern.hook {
-                lines.push(format!("{}{hook}(asm, node, module);", construct_tabs(close)))
+        Ok(quote! {
+            /// What `select_with` optimizes for if multiple patterns with costs apply
+            ///
+            /// The functions without a goal (`compile`, `tmps`, ...) use the default
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+            #vis enum CostGoal {
+                /// the fewest instructions
+                #[default]
+                Instrs,
+                Latency,
+                Size,
             }
 
+            /// Selects the pattern for `node` with the default goal (see [`select_with`])
+            #vis fn select(node: &#node) -> Option<PatternId> {
+                select_with(node, CostGoal::default())
+            }
 
-            lines.push(format!("{}return;", construct_tabs(close)));
-    
-            let close_clone = close;
-    
-            for _ in 0..close_clone {
-                close -= 1;
-                lines.push(format!("{}}}", construct_tabs(close)));
+            /// Selects the cheapest applicable pattern for `goal`, or the first
+            /// applicable one for opcodes whose patterns declare no costs
+            #[allow(unused_variables)]
+            #vis fn select_with(node: &#node, goal: CostGoal) -> Option<PatternId> {
+                match node.get_opcode() {
+                    #(#arms)*
+                    _ => None,
+                }
             }
+        })
+    }
 
-            if let Some(func) = funcs.get_mut(&compile_fn) {
-                func.extend_from_slice(&lines);
-            } else {
-                funcs.insert(compile_fn, lines);
+    /// The conditions under which the pattern applies to `node`
+    fn conditions(&self, paths: &Paths, pat: &ast::Pattern) -> Result<Vec<TokenStream>, Error> {
+        let mut conds = Vec::new();
+
+        let ops = [pat.variant.ls, pat.variant.rs, pat.variant.op3];
+
+        for (index, op) in ops.into_iter().enumerate() {
+            if let Some(op) = op {
+                if op != ast::OpVariant::Any {
+                    let func = format_ident!("is_op_{}", op.to_string());
+                    let index = Literal::usize_unsuffixed(index);
+                    conds.push(quote!(node.#func(#index)));
+                }
             }
         }
 
-        for (name, lines) in &funcs {
-            let func = scope.new_fn(name)
-            .arg("asm", asm_vec)
-            .arg("node", "DagNode")
-            .arg("module", "&mut crate::IR::Module");
-            for line in lines {
-                func.line(line);
+        for (index, op) in pat.variant.tree.iter().enumerate() {
+            conds.extend(tree_condition(pat, index, op)?);
+        }
+
+        if let Some(out) = pat.variant.out {
+            if out != ast::OpVariant::Any {
+                let func = format_ident!("is_out_{}", out.to_string());
+                conds.push(quote!(node.#func()));
             }
-            func.line("todo!(\"not yet compilable variant: {} ({})\", node, node.get_ty())");
         }
 
-        // now handle the temporarys
-        self.gen_tmps(&mut scope, target);
+        if let Some(ty) = &pat.variant.ty {
+            match type_predicate(ty) {
+                Some(properties) => conds.extend(properties.iter().map(|(property, holds)| {
+                    let func = match property {
+                        TypeProperty::Int => quote!(intenger),
+                        TypeProperty::Signed => quote!(signed),
+                        TypeProperty::Float => quote!(float),
+                    };
+
+                    if *holds { quote!(node.get_ty().#func()) } else { quote!(!node.get_ty().#func()) }
+                })),
+                None => {
+                    let ty = type_metadata(paths, pat, ty)?;
+                    conds.push(quote!(node.is_ty(#ty)));
+                },
+            }
+        }
 
-        // handle the overwrites
-        self.gen_overwrittes(&mut scope, target);
+        Ok(conds)
+    }
 
-        let code = scope.to_string();
-        let code = code.replace("fn", "pub fn"); // make all functions public
+    /// Builds the match arm compiling the pattern and whether it needs `expand_li`
+    fn construct_arm(&self, target: ast::AstTarget, paths: &Paths, id: usize, pattern: &ast::Pattern) -> Result<(TokenStream, bool), Error> {
+        let id = Literal::usize_unsuffixed(id);
+
+        let marker = marker(pattern);
+        let trace = construct_trace(paths, &id, pattern);
+        let operands = construct_operands(paths, pattern);
+        let tie = construct_tie(target, paths, pattern)?;
+        let (body, expands_li) = construct_body(target, paths, pattern)?;
+
+        let hook = match &pattern.hook {
+            Some(hook) => {
+                let hook = syn::parse_str::<syn::Path>(hook).map_err(|err| pattern_error(pattern, pattern.loc, format!("invalid hook: {}", err)))?;
+                quote!(#hook(asm, node, module);)
+            },
+            None => TokenStream::new(),
+        };
+
+        let ok = if paths.fallible { quote!(Ok(())) } else { TokenStream::new() };
+
+        let arm = quote! {
+            #marker
+            Some(#id) => {
+                #trace
+                #operands
+                #tie
+                #body
+                #hook
+                #ok
+            }
+        };
 
-        format!("#[allow(warnings)]\n{code}")
+        Ok((arm, expands_li))
     }
 
-    fn construct_cond(&self, pat: &ast::Pattern) -> String {
-        let mut cond = String::new();
+    fn gen_tmps(&self, paths: &Paths) -> Result<TokenStream, Error> {
+        let Paths { node, tmp_info, vis, .. } = paths;
+
+        let mut arms = Vec::new();
+
+        for (id, pat) in self.patterns.iter().enumerate() {
+            let operands = leaves(pat).into_iter()
+                .map(|leaf| {
+                    let tmp = operand_tmp(leaf.tmp);
+                    let op = operand_expr(&leaf.path);
+                    quote!(tmps.extend_from_slice(&OperationHandler::new().tmp(&#op, #tmp));)
+                })
+                .collect::<Vec<_>>();
+
+            let mut maps = Vec::new();
+
+            for tmp in &pat.maps {
+                let num = tmp_number(pat, tmp)?;
+
+                let func = match (&tmp.reg, tmp.ty) {
+                    (Some(reg), _) => {
+                        let reg_ty = &paths.reg;
+                        let reg = format_ident!("{}", reg);
+                        quote!(tmp.require_reg(#reg_ty::#reg()))
+                    },
+                    (None, ast::OpVariant::Gr) => quote!(tmp.require_gr()),
+                    (None, ast::OpVariant::Fp) => quote!(tmp.require_fp()),
+                    (None, ast::OpVariant::Mem) => quote!(tmp.require_mem()),
+                    (None, ast::OpVariant::Imm) => return Err(pattern_error(pat, pat.loc, "tmps cannot have imm as their type")),
+                    (None, ast::OpVariant::Any) => return Err(pattern_error(pat, pat.loc, "tmporarys cannot have any type")),
+                };
+
+                let size = match &tmp.size {
+                    Some(size) => type_metadata(paths, pat, size)?,
+                    None => quote!(node.get_ty()),
+                };
+
+                let num = Literal::usize_unsuffixed(num);
 
-        cond.push_str(&format!("if let DagOpCode::{} = node.get_opcode()  {{", pat.variant.mnemonic));
+                maps.push(quote! {
+                    let mut tmp = #tmp_info::new(#num, #size);
+                    tmp.size = #size;
+                    #func;
+                    tmps.push(tmp);
+                });
+            }
 
-        cond.push_str("if true ");
+            if let Some(kind) = tie_tmp(pat) {
+                let func = match kind {
+                    ast::OpVariant::Fp => quote!(tmp.require_fp()),
+                    _ => quote!(tmp.require_gr()),
+                };
 
-        if let Some(ls) = pat.variant.ls {
-            if ls != ast::OpVariant::Any {
-                cond.push_str(&format!(" && node.is_op_{ls}(0)"));
+                maps.push(quote! {
+                    let mut tmp = #tmp_info::new(TIE_TMP, node.get_ty());
+                    tmp.size = node.get_ty();
+                    #func;
+                    tmps.push(tmp);
+                });
             }
+
+            if operands.is_empty() && maps.is_empty() { continue; }
+
+            let id = Literal::usize_unsuffixed(id);
+            let marker = marker(pat);
+
+            arms.push(quote! {
+                #marker
+                Some(#id) => {
+                    let mut tmps = Vec::new();
+                    #(#operands)*
+                    #(#maps)*
+                    tmps
+                }
+            });
         }
 
-        if let Some(rs) = pat.variant.rs {
-            if rs != ast::OpVariant::Any {
-                cond.push_str(&format!(" && node.is_op_{rs}(1)"));
+        let tie_tmp = if self.patterns.iter().any(|pat| tie_tmp(pat).is_some()) {
+            quote! {
+                /// The temporary a tied pattern saves its other operand in if that
+                /// operand is `$out`, it has the class of the operand and the type of the node
+                #vis const TIE_TMP: usize = 0xEF;
             }
+        } else {
+            TokenStream::new()
+        };
+
+        Ok(quote! {
+            #tie_tmp
+
+            /// The temporaries the instructions for `node` need
+            #vis fn tmps(node: &#node) -> Vec<#tmp_info> {
+                tmps_with(node, CostGoal::default())
+            }
+
+            /// The temporaries of the pattern `compile_with` uses for `goal`
+            #vis fn tmps_with(node: &#node, goal: CostGoal) -> Vec<#tmp_info> {
+                match select_with(node, goal) {
+                    #(#arms)*
+                    _ => Vec::new(),
+                }
+            }
+        })
+    }
+
+    fn gen_overwrittes(&self, paths: &Paths) -> Result<TokenStream, Error> {
+        let Paths { node, reg, vis, .. } = paths;
+
+        let mut arms = Vec::new();
+
+        for (id, pat) in self.patterns.iter().enumerate() {
+            if pat.overwrittes.is_empty() { continue; }
+
+            let id = Literal::usize_unsuffixed(id);
+            let overwrittes = pat.overwrittes.iter().map(|overwrite| format_ident!("{}", overwrite));
+            let marker = marker(pat);
+
+            arms.push(quote! {
+                #marker
+                Some(#id) => vec![#(#reg::#overwrittes()),*],
+            });
         }
-        
-        if let Some(op3) = pat.variant.op3 {
-            if op3 != ast::OpVariant::Any {
-                cond.push_str(&format!(" && node.is_op_{op3}(2)"));
+
+        Ok(quote! {
+            /// The registers the instructions for `node` overwrite
+            #vis fn overwrittes(node: &#node) -> Vec<#reg> {
+                overwrittes_with(node, CostGoal::default())
+            }
+
+            /// The registers the pattern `compile_with` uses for `goal` overwrites
+            #vis fn overwrittes_with(node: &#node, goal: CostGoal) -> Vec<#reg> {
+                match select_with(node, goal) {
+                    #(#arms)*
+                    _ => Vec::new(),
+                }
             }
+        })
+    }
+
+    /// Generates the `Mnemonic` enum out of the `def Inst` declarations
+    fn gen_mnemonics(&self, paths: &Paths) -> Result<TokenStream, Error> {
+        let Paths { vis, .. } = paths;
+
+        if self.insts.is_empty() {
+            return Ok(TokenStream::new());
         }
 
-        if let Some(out) = pat.variant.out {
-            if out != ast::OpVariant::Any {
-                cond.push_str(&format!(" && node.is_out_{out}()"));
+        let mut variants = Vec::new();
+
+        for inst in &self.insts {
+            match syn::parse_str::<syn::Ident>(&variant_name(&inst.name)) {
+                Ok(variant) => variants.push(variant),
+                Err(_) => return Err(Error::new(Some(inst.loc), format!("invalid instruction name: {}", inst.name)).in_file(&inst.file)),
             }
         }
-        
-        if let Some(ty) = &pat.variant.ty {
-            if ty.contains("<") && ty.contains(">") {
-                // vector type
 
-                let ty = ty.replace(">", "").replace("<", "");
+        let names: Vec<&String> = self.insts.iter().map(|inst| &inst.name).collect();
+        let forms = self.insts.iter().map(|inst| {
+            let forms = inst.forms.iter().map(|form| {
+                let operands = &form.operands;
+                quote!(&[#(#operands),*])
+            });
+            quote!(&[#(#forms),*])
+        });
+
+        Ok(quote! {
+            /// The instructions declared with `def Inst`
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+            #vis enum Mnemonic {
+                #(#variants,)*
+            }
 
-                let ty = ty.split("x").collect::<Vec<&str>>();
+            impl Mnemonic {
+                /// The operand forms the instruction accepts (e.g. `["r/m64", "imm32"]`)
+                #vis fn forms(&self) -> &'static [&'static [&'static str]] {
+                    match self {
+                        #(Mnemonic::#variants => #forms,)*
+                    }
+                }
+            }
 
-                let size = ty.get(0).expect("expected size for vector types");
-                let ty = ty.get(1).expect("expected type");
+            impl std::fmt::Display for Mnemonic {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    let name = match self {
+                        #(Mnemonic::#variants => #names,)*
+                    };
 
-                cond.push_str(&format!("&& node.is_ty(crate::IR::TypeMetadata::Vector(crate::IR::VecTy {{ size: {size}, ty: crate::IR::StdTypeMetadata::{ty}}}))"));
-            } else {
-                match ty.as_str() {
-                    "int" => cond.push_str(" && node.get_ty().intenger()"),
-                    "signed" => cond.push_str(" && node.get_ty().signed()"),
-                    "unsigned" => cond.push_str(" && !node.get_ty().signed()"),
-                    "float" => cond.push_str(" && node.get_ty().float()"),
-                    "no_float" => cond.push_str(" && !node.get_ty().float()"),
-                    _ => cond.push_str(&format!(" && node.is_ty(crate::IR::TypeMetadata::{})",  ty)),
+                    f.write_str(name)
                 }
             }
-        }
 
-        cond
+            impl std::str::FromStr for Mnemonic {
+                type Err = ();
+
+                /// The instruction with the assembly name
+                fn from_str(name: &str) -> Result<Self, Self::Err> {
+                    match name {
+                        #(#names => Ok(Mnemonic::#variants),)*
+                        _ => Err(()),
+                    }
+                }
+            }
+        })
+    }
+
+    /// Whether some x86 encoded form has 8 bit registers
+    fn has_byte_regs(&self) -> bool {
+        self.insts.iter().flat_map(|inst| &inst.forms)
+            .any(|form| matches!(form.encoding, Some(ast::Encoding::X86(_))) && !byte_regs(form).is_empty())
     }
 
-    fn gen_tmps(&self, scope: &mut Scope, _target: ast::AstTarget) {
-        let tmp_req_func = scope.new_fn("tmps")
-            .arg("node", "&dag::DagNode")
-            .ret("Vec<dag::DagTmpInfo>");
+    /// Generates `encode` out of the encodings of the `def Inst` forms
+    ///
+    /// Requires `Asm::mnemonic`, `Asm::operands`, `Reg::enc` (the register
+    /// number) and `base`, `index`, `scale` and `displ` on `MemoryDispl`. If an
+    /// x86 form has 8 bit registers (`r8`, `r/m8`), `Reg::is_high_byte` tells
+    /// ah, ch, dh and bh (numbers 4 to 7 without a rex prefix) from spl, bpl, sil and dil
+    fn gen_encode(&self, paths: &Paths) -> Result<TokenStream, Error> {
+        let Paths { asm, vis, .. } = paths;
 
-        for pat in &self.patterns {
-            tmp_req_func.line(format!("{} {{", self.construct_cond(&pat)));
+        let encodings = || self.insts.iter().flat_map(|inst| &inst.forms).filter_map(|form| form.encoding.as_ref());
 
-            tmp_req_func.line("\tlet mut tmps = Vec::new();");
+        if encodings().next().is_none() {
+            return Ok(TokenStream::new());
+        }
 
-            if let Some(_) = pat.variant.ls {
-                tmp_req_func.line("\tlet ls_tmps = OperationHandler::new().tmp(&node.get_op(0), 0xF0);");
-                tmp_req_func.line("\ttmps.extend_from_slice(&ls_tmps);");
-            }
-            if let Some(_) = pat.variant.rs {
-                tmp_req_func.line("\tlet ls_tmps = OperationHandler::new().tmp(&node.get_op(1), 0xF1);");
-                tmp_req_func.line("\ttmps.extend_from_slice(&ls_tmps);");
-            }
+        let byte_regs = self.has_byte_regs();
 
-            for tmp in &pat.maps {
-                let num = tmp.var.replace("%t", "");
+        let mut arms = Vec::new();
+
+        for inst in &self.insts {
+            let variant = format_ident!("{}", variant_name(&inst.name));
+            let mut checks = Vec::new();
+
+            for form in &inst.forms {
+                let Some(encoding) = &form.encoding else { continue };
+
+                let specs: Vec<ast::OperandSpec> = form.operands.iter().map(|op| ast::OperandSpec::of(op)).collect();
+                let count = Literal::usize_unsuffixed(specs.len());
 
-                tmp_req_func.line(format!("\tlet mut tmp = dag::DagTmpInfo::new({num}, node.get_ty());"));
+                let mut conds = vec![quote!(ops.len() == #count)];
+                conds.extend((0..specs.len()).map(|index| spec_condition(form, index)));
+                let cond = all(&conds);
 
-                let func = match tmp.ty {
-                    ast::OpVariant::Gr => "tmp.require_gr()",
-                    ast::OpVariant::Fp => "tmp.require_fp()",
-                    ast::OpVariant::Mem => "tmp.require_mem()",
-                    ast::OpVariant::Imm => panic!("tmps cannot have imm as their type"),
-                    ast::OpVariant::Any => panic!("tmporarys cannot have any type"),
+                let table = encoding_table(encoding, form, byte_regs);
+
+                let call = match encoding {
+                    ast::Encoding::X86(_) => quote!(encode_x86(#table, ops, out)),
+                    ast::Encoding::Bits(_) => quote!(encode_bits(#table, ops, out)),
                 };
 
-                tmp_req_func.line("\ttmp.size = node.get_ty();");
+                let loc = format!("{}:{}", inst.file, form.loc.line);
 
-                tmp_req_func.line(format!("\t{func};"));
-                tmp_req_func.line("\ttmps.push(tmp);");
+                checks.push(quote! {
+                    #[ytbgen = #loc]
+                    if #cond {
+                        return #call.map_err(|_| {
+                            out.truncate(len);
+                            EncodeError::UnencodableOperand(mnemonic)
+                        });
+                    }
+                });
+            }
 
+            if !checks.is_empty() {
+                arms.push(quote!(Mnemonic::#variant => { #(#checks)* }));
             }
-            tmp_req_func.line("\treturn tmps;");
-            tmp_req_func.line("}\t}");
         }
-        tmp_req_func.line("Vec::new()");
+
+        let x86 = if encodings().any(|enc| matches!(enc, ast::Encoding::X86(_))) { gen_encode_x86(byte_regs) } else { TokenStream::new() };
+        let bits = if encodings().any(|enc| matches!(enc, ast::Encoding::Bits(_))) { gen_encode_bits() } else { TokenStream::new() };
+
+        Ok(quote! {
+            /// Why an instruction could not be encoded
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            #vis enum EncodeError {
+                /// no encoded form of the instruction accepts the operands
+                NoForm(Mnemonic),
+                /// an operand has no encoding (e.g. a temporary which was not allocated)
+                UnencodableOperand(Mnemonic),
+            }
+
+            impl std::fmt::Display for EncodeError {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    match self {
+                        EncodeError::NoForm(mnemonic) => write!(f, "no encoding of `{}` accepts the operands", mnemonic),
+                        EncodeError::UnencodableOperand(mnemonic) => write!(f, "an operand of `{}` can't be encoded", mnemonic),
+                    }
+                }
+            }
+
+            impl std::error::Error for EncodeError {}
+
+            /// Appends the machine code of `asm` to `out`
+            ///
+            /// The first form of the instruction which accepts the operands is used
+            #[allow(unused_variables, unreachable_code)]
+            #vis fn encode(asm: &#asm, out: &mut Vec<u8>) -> Result<(), EncodeError> {
+                let mnemonic = asm.mnemonic();
+                let ops = asm.operands();
+                let len = out.len();
+
+                match mnemonic {
+                    #(#arms)*
+                    _ => {}
+                }
+
+                Err(EncodeError::NoForm(mnemonic))
+            }
+
+            #x86
+
+            #bits
+        })
     }
 
-    fn gen_overwrittes(&self, scope: &mut Scope, _target: ast::AstTarget) {
-        let func = scope.new_fn("overwrittes") 
-            .arg("node", "&dag::DagNode")
-            .ret("Vec<Reg>");
+    /// Generates `decode` out of the same encodings as `encode`
+    ///
+    /// Requires `Reg::from_enc(number, size)` (the size in bits of the operand
+    /// form, 0 if it has none) and `MemoryDispl::from_parts(base, index, scale, displ)`.
+    /// If an x86 form has 8 bit registers, `Reg::high_byte(number)` is ah, ch, dh
+    /// or bh for the numbers 4 to 7
+    fn gen_decode(&self, paths: &Paths) -> TokenStream {
+        let Paths { asm, vis, .. } = paths;
+
+        let mut checks = Vec::new();
+        let (mut x86, mut bits) = (false, false);
+        let byte_regs = self.has_byte_regs();
+
+        for inst in &self.insts {
+            let variant = format_ident!("{}", variant_name(&inst.name));
+
+            for form in &inst.forms {
+                let Some(encoding) = &form.encoding else { continue };
+
+                let kinds: Vec<ast::OperandSpec> = form.operands.iter().map(|op| ast::OperandSpec::of(op)).collect();
+                let table = encoding_table(encoding, form, byte_regs);
+
+                let specs = form.operands.iter().zip(&kinds).map(|(op, kind)| {
+                    // `r/m64` -> 64, `r` -> 0
+                    let digits = op.len() - op.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+                    let size = Literal::usize_unsuffixed(op[op.len() - digits..].parse().unwrap_or(0));
+
+                    match kind {
+                        ast::OperandSpec::Reg => quote!(OperandSpec::Reg(#size)),
+                        ast::OperandSpec::Mem => quote!(OperandSpec::Mem),
+                        ast::OperandSpec::RegOrMem => quote!(OperandSpec::RegOrMem(#size)),
+                        ast::OperandSpec::Imm(width) => {
+                            let width = Literal::u32_unsuffixed(*width);
+                            quote!(OperandSpec::Imm(#width))
+                        },
+                        ast::OperandSpec::UImm(width) => {
+                            let width = Literal::u32_unsuffixed(*width);
+                            quote!(OperandSpec::UImm(#width))
+                        },
+                    }
+                });
+
+                let call = match encoding {
+                    ast::Encoding::X86(_) => {
+                        x86 = true;
+                        quote!(decode_x86(#table, &[#(#specs),*], bytes))
+                    },
+                    ast::Encoding::Bits(_) => {
+                        bits = true;
+                        quote!(decode_bits(#table, &[#(#specs),*], bytes))
+                    },
+                };
 
-        for pat in &self.patterns {
-            if pat.overwrittes.is_empty() { continue; }
-            func.line(format!("{} {{", self.construct_cond(pat)));
-            
-            func.line("\tlet mut overwrittes = Vec::new();");
-            for overwrite in &pat.overwrittes {
-                func.line(format!("\toverwrittes.push(Reg::{overwrite}());"));
+                let with = format_ident!("with{}", form.operands.len());
+                let args = form.operands.iter().map(|_| quote!(ops.next()?));
+                let loc = format!("{}:{}", inst.file, form.loc.line);
+
+                checks.push(quote! {
+                    #[ytbgen = #loc]
+                    if let Some((ops, len)) = #call {
+                        let mut ops = ops.into_iter();
+                        return Some((#asm::#with(Mnemonic::#variant, #(#args),*), len));
+                    }
+                });
             }
-            func.line("\treturn overwrittes;");
+        }
 
-            func.line("}}");
+        if checks.is_empty() {
+            return TokenStream::new();
         }
 
-        func.line("Vec::new()");
-    }
-}
+        let x86 = if x86 { gen_decode_x86(paths, byte_regs) } else { TokenStream::new() };
+        let bits = if bits { gen_decode_bits(paths) } else { TokenStream::new() };
+
+        quote! {
+            /// What an operand of an instruction form accepts, with the size of
+            /// registers and the width of immediates in bits
+            #[derive(Clone, Copy)]
+            #[allow(dead_code)]
+            enum OperandSpec {
+                Reg(usize),
+                Mem,
+                RegOrMem(usize),
+                Imm(u32),
+                UImm(u32),
+            }
 
-fn first_to_uppercase(s: &str) -> String {
-    let mut c = s.chars();
-    match c.next() {
-        None => String::new(),
-        Some(f) => f.to_uppercase().collect::<String>() + c.as_str(),
-    }
-}
+            /// Decodes the instruction at the start of `bytes` into the asm and its length in bytes
+            ///
+            /// The forms are tried in the order of the .td file, so `encode` and `decode` round trip
+            #[allow(unused_mut)]
+            #vis fn decode(bytes: &[u8]) -> Option<(#asm, usize)> {
+                #(#checks)*
 
-fn construct_asm(target: ast::AstTarget, pattern: &ast::Pattern, code: &mut Vec<String>, mut close: usize, tabs: fn(usize) -> String) {
-    // construct operand generation code
-
-    if let Some(_ls) = pattern.variant.ls {
-        code.push(format!("{}let ls = {{", tabs(close)));
-        close += 1;
-
-        code.push(format!("{}let mut consta = None;", tabs(close)));
-        code.push(format!("{}if OperationHandler::new().requires_new_const(&node.get_op(0)) {{ consta = Some(OperationHandler::new().create_const(module)) }}", tabs(close)));
-
-        code.push(format!("{}if OperationHandler::new().just_op(&node.get_op(0)) {{", tabs(close)));
-        close += 1;
-        code.push(format!("{}OperationHandler::new().compile_op(&node.get_op(0), consta.as_ref()).unwrap()", tabs(close)));
-        close -= 1;
-        code.push(format!("{}}} else if OperationHandler::new().inserts_instrs(&node.get_op(0)) {{", tabs(close)));
-        close += 1;
-        code.push(format!("{}let Some(instrs) = OperationHandler::new().compile_instrs(&node.get_op(0), consta.as_ref(), DagTmpInfo::new(0xF0, node.get_ty())) else {{ panic!() }};", tabs(close)));
-        code.push(format!("{}asm.extend_from_slice(&instrs);", tabs(close)));
-        code.push(format!("{}Operand::Tmp(0xF0)", tabs(close)));
-        close -= 1;
-        code.push(format!("{}}} else {{ panic!() }}", tabs(close)));
-        close -= 1;
-        code.push(format!("{}}};", tabs(close)));
-    }
-
-    if let Some(_rs) = pattern.variant.rs {
-        code.push(format!("{}let rs = {{", tabs(close)));
-        close += 1;
-
-        code.push(format!("{}let mut consta = None;", tabs(close)));
-        code.push(format!("{}if OperationHandler::new().requires_new_const(&node.get_op(1)) {{ consta = Some(OperationHandler::new().create_const(module)) }}", tabs(close)));
-
-        code.push(format!("{}if OperationHandler::new().just_op(&node.get_op(1)) {{", tabs(close)));
-        close += 1;
-        code.push(format!("{}OperationHandler::new().compile_op(&node.get_op(1), consta.as_ref()).unwrap()", tabs(close)));
-        close -= 1;
-        code.push(format!("{}}} else if OperationHandler::new().inserts_instrs(&node.get_op(1)) {{", tabs(close)));
-        close += 1;
-        code.push(format!("{}let Some(instrs) = OperationHandler::new().compile_instrs(&node.get_op(1), consta.as_ref(), DagTmpInfo::new(0xF1, node.get_ty())) else {{ panic!() }};", tabs(close)));
-        code.push(format!("{}asm.extend_from_slice(&instrs);", tabs(close)));
-        code.push(format!("{}Operand::Tmp(0xF1)", tabs(close)));
-        close -= 1;
-        code.push(format!("{}}} else {{ panic!() }}", tabs(close)));
-        close -= 1;
-        code.push(format!("{}}};", tabs(close)));
-    }
-
-    if let Some(_op3) = pattern.variant.op3 {
-        code.push(format!("{}let op3 = {{", tabs(close)));
-        close += 1;
-
-        code.push(format!("{}let mut consta = None;", tabs(close)));
-        code.push(format!("{}if OperationHandler::new().requires_new_const(&node.get_op(2)) {{ consta = Some(OperationHandler::new().create_const(module)) }}", tabs(close)));
-
-        code.push(format!("{}if OperationHandler::new().just_op(&node.get_op(2)) {{", tabs(close)));
-        close += 1;
-        code.push(format!("{}OperationHandler::new().compile_op(&node.get_op(2), consta.as_ref()).unwrap()", tabs(close)));
-        close -= 1;
-        code.push(format!("{}}} else if OperationHandler::new().inserts_instrs(&node.get_op(2)) {{", tabs(close)));
-        close += 1;
-        code.push(format!("{}let Some(instrs) = OperationHandler::new().compile_instrs(&node.get_op(2), consta.as_ref(), DagTmpInfo::new(0xF2, node.get_ty())) else {{ panic!() }};", tabs(close)));
-        code.push(format!("{}asm.extend_from_slice(&instrs);", tabs(close)));
-        code.push(format!("{}Operand::Tmp(0xF2)", tabs(close)));
-        close -= 1;
-        code.push(format!("{}}} else {{ panic!() }}", tabs(close)));
-        close -= 1;
-        code.push(format!("{}}};", tabs(close)));
-    }
-
-    // construct assembly build
+                None
+            }
 
-    for line in &pattern.lines {
-        match line {
-            AsmLine::Rust(rust) => code.push(rust.to_string()),
-            AsmLine::Asm(asm) => code.push(format!("asm.push({});", construct_assembly_build(target, asm.replace("\n", "")))),
-        }
-    }
-}
-
-fn construct_assembly_build(target: ast::AstTarget, line: String) -> String {
-    let mut builder = String::from("Asm::");
-    
-    let mut arg_string = String::new();
-    
-    let line = line.trim();
-    let line = line.replace(", ", " ");
-    let line = line.replace("[", "[ ");
-    let line = line.replace("]", " ]");
-    
-    let mut split = line.split(' ').collect::<Vec<&str>>();
-    split.reverse();
-    
-    let mnemonic = split.pop().expect("expected mnemonic");
-    let mnemonic = first_to_uppercase(mnemonic);
-    
-    arg_string.push_str(&format!("Mnemonic::{}", mnemonic));
-    
-    let num_args = target_specific_argument_parsing(target, &mut arg_string, &mut split);
-    
-    arg_string.push(')');
-    
-    let arg_string = arg_string.replace("$out", "node.get_out().into()");
-    let arg_string = arg_string.replace("$1", "ls");
-    let arg_string = arg_string.replace("$2", "rs");
-    let arg_string = arg_string.replace("$3", "op3");
-    
-    let arg_string = arg_string.replace("%t0", &format!("Operand::Tmp(0)"));
-    let arg_string = arg_string.replace("%t1", &format!("Operand::Tmp(1)"));
-    let arg_string = arg_string.replace("%t2", &format!("Operand::Tmp(2)"));
-    
-    builder.push_str(&format!("with{num_args}"));
-    builder.push('(');
-
-    builder.push_str(&arg_string);
-
-    builder
-}
-
-fn target_specific_argument_parsing(target: ast::AstTarget, builder: &mut String, tokens: &mut Vec<&str>) -> usize{
-    match target {
-        ast::AstTarget::X86 => x86_specifc_arg(builder, tokens),
+            #x86
+
+            #bits
+        }
     }
-}
 
-fn x86_specifc_arg(builder: &mut String, tokens: &mut Vec<&str>) -> usize {
-    let mut amount = 0;
+    /// Generates the assembly text parser named by `asm_parser`
+    ///
+    /// The operands are parsed in the syntax of the target and checked against
+    /// the forms of the instruction. The first form taking the operands is the
+    /// one encoded, the size of x86 memory operands (`byte ptr`) has to be the
+    /// size of its operand. Mnemonics are taken
+    /// as written or in lowercase (`ADD` is `add`). Requires `FromStr` on
+    /// `Reg` and `MemoryDispl::from_parts(base, index, scale, displ)`
+    fn gen_asm_parser(&self, target: ast::AstTarget, paths: &Paths) -> Result<TokenStream, Error> {
+        let Paths { asm, vis, .. } = paths;
+
+        let Some(name) = &self.asm_parser else {
+            return Ok(TokenStream::new());
+        };
+
+        let name: syn::Ident = parse_config("asm_parser", name)?;
+
+        if self.insts.is_empty() {
+            return Err(Error::new(None, "`asm_parser` needs instructions declared with `def Inst`"));
+        }
+
+        let mut arms = Vec::new();
+
+        for inst in &self.insts {
+            let variant = format_ident!("{}", variant_name(&inst.name));
+
+            // the first form which takes the operands is the one encoded, so its sizes have to fit
+            let forms = inst.forms.iter().map(|form| {
+                let count = Literal::usize_unsuffixed(form.operands.len());
+
+                let mut conds = vec![quote!(ops.len() == #count)];
+                conds.extend((0..form.operands.len()).map(|index| spec_condition(form, index)));
+                let cond = all(&conds);
+
+                let sizes = match target {
+                    ast::AstTarget::X86 => (0..form.operands.len()).filter_map(|index| size_condition(form, index)).collect(),
+                    _ => Vec::new(),
+                };
+                let sizes = all(&sizes);
+
+                quote!(if #cond { Some(#sizes) })
+            });
+
+            arms.push(quote!(Mnemonic::#variant => #(#forms)else* else { None },));
+        }
+
+        let mut counts = self.insts.iter().flat_map(|inst| &inst.forms).map(|form| form.operands.len()).collect::<Vec<_>>();
+        counts.sort();
+        counts.dedup();
+
+        let builds = counts.into_iter().map(|count| {
+            let with = format_ident!("with{}", count);
+            let args = (0..count).map(|_| quote!(op()));
+            let count = Literal::usize_unsuffixed(count);
+            quote!(#count => #asm::#with(mnemonic #(, #args)*),)
+        });
+
+        let comments = match target {
+            ast::AstTarget::X86 => quote!([';', '#']),
+            ast::AstTarget::AArch64 => quote!("//"),
+            ast::AstTarget::RiscV => quote!('#'),
+        };
+
+        let parse_operand = gen_parse_operand(target);
+
+        // the sizes written in front of x86 memory operands
+        let sizes = match target {
+            ast::AstTarget::X86 => quote!(let sizes = texts.iter().map(|op| ptr_size(op)).collect::<Vec<_>>();),
+            _ => TokenStream::new(),
+        };
+
+        Ok(quote! {
+            /// Why assembly text could not be parsed, with the line (starting at 1)
+            #[derive(Debug, Clone, PartialEq, Eq)]
+            #vis enum AsmParseError {
+                /// the line does not start with an instruction declared with `def Inst`
+                UnknownMnemonic(usize, String),
+                /// the operand is no register, immediate or memory operand
+                InvalidOperand(usize, String),
+                /// no form of the instruction takes the operands
+                NoForm(usize, Mnemonic),
+                /// the size of a memory operand (`byte ptr`) is not the one of the
+                /// first form which takes the operands, which is the form encoded
+                SizeMismatch(usize, Mnemonic),
+            }
+
+            impl std::fmt::Display for AsmParseError {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    match self {
+                        AsmParseError::UnknownMnemonic(line, name) => write!(f, "{}: unknown instruction `{}`", line, name),
+                        AsmParseError::InvalidOperand(line, op) => write!(f, "{}: invalid operand `{}`", line, op),
+                        AsmParseError::NoForm(line, mnemonic) => write!(f, "{}: no form of `{}` takes the operands", line, mnemonic),
+                        AsmParseError::SizeMismatch(line, mnemonic) => write!(f, "{}: the operand size does not fit the first form of `{}` which takes the operands", line, mnemonic),
+                    }
+                }
+            }
+
+            impl std::error::Error for AsmParseError {}
+
+            /// Parses assembly text with one instruction per line
+            #vis fn #name(text: &str) -> Result<Vec<#asm>, AsmParseError> {
+                let mut asm = Vec::new();
+
+                for (index, line) in text.lines().enumerate() {
+                    let line = line.split(#comments).next().unwrap_or_default().trim();
+
+                    if !line.is_empty() {
+                        asm.push(parse_asm_line(index + 1, line)?);
+                    }
+                }
+
+                Ok(asm)
+            }
+
+            #[allow(unused_variables, unused_mut)]
+            fn parse_asm_line(line: usize, text: &str) -> Result<#asm, AsmParseError> {
+                let (name, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+
+                let Ok(mnemonic) = name.parse::<Mnemonic>().or_else(|_| name.to_ascii_lowercase().parse()) else {
+                    return Err(AsmParseError::UnknownMnemonic(line, name.to_string()));
+                };
 
-    while tokens.len() > 0 {
-        builder.push_str(", ");
+                let texts = split_operands(rest);
 
-        let Some(tok) = tokens.pop() else { break; };
+                let ops = texts.iter()
+                    .map(|op| parse_operand(op).ok_or_else(|| AsmParseError::InvalidOperand(line, op.to_string())))
+                    .collect::<Result<Vec<Operand>, _>>()?;
 
-        if tok != "[" { // no memory displacment
-            builder.push_str(tok);
-        } else { // memory displacment
-            builder.push_str("MemoryDispl::new(");
-            let mut first = true;
-            loop {
-                if !first {
-                    builder.push_str(", ");
+                #sizes
+
+                let fits = match mnemonic {
+                    #(#arms)*
+                };
+
+                match fits {
+                    None => return Err(AsmParseError::NoForm(line, mnemonic)),
+                    Some(false) => return Err(AsmParseError::SizeMismatch(line, mnemonic)),
+                    Some(true) => {},
                 }
 
-                let Some(tok) = tokens.pop() else { panic!("no end to mem displacment") };
+                let count = ops.len();
+                let mut ops = ops.into_iter();
+                let mut op = || ops.next().expect("the form has the operand");
+
+                Ok(match count {
+                    #(#builds)*
+                    _ => unreachable!("no form has {} operands", count),
+                })
+            }
+
+            /// Splits the operands at the commas which are not inside of brackets
+            fn split_operands(text: &str) -> Vec<&str> {
+                let mut ops = Vec::new();
+                let mut depth = 0;
+                let mut start = 0;
+
+                for (pos, c) in text.char_indices() {
+                    match c {
+                        '[' | '(' => depth += 1,
+                        ']' | ')' => depth -= 1,
+                        ',' if depth == 0 => {
+                            ops.push(text[start..pos].trim());
+                            start = pos + 1;
+                        },
+                        _ => {},
+                    }
+                }
 
-                if tok == "]" { break }
-                
-                match tok {
-                    "+" => builder.push_str("MemoryOption::Plus"),
-                    "-" => builder.push_str("MemoryOption::Minus"),
-                    _ => builder.push_str(tok),
+                if !ops.is_empty() || !text[start..].trim().is_empty() {
+                    ops.push(text[start..].trim());
                 }
 
-                first = false;
+                ops
+            }
+
+            /// Parses a decimal, hex (`0x`) or binary (`0b`) integer
+            ///
+            /// Unsigned values which don't fit into an i64 wrap around (`0xffffffffffffffff` is -1)
+            fn parse_imm(text: &str) -> Option<i64> {
+                let (negative, digits) = match text.strip_prefix('-') {
+                    Some(digits) => (true, digits),
+                    None => (false, text),
+                };
+
+                let digits = digits.replace('_', "");
+
+                let value = if let Some(hex) = digits.strip_prefix("0x") {
+                    u64::from_str_radix(hex, 16).ok()?
+                } else if let Some(bin) = digits.strip_prefix("0b") {
+                    u64::from_str_radix(bin, 2).ok()?
+                } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+                    digits.parse::<u64>().ok()?
+                } else {
+                    return None;
+                };
+
+                if negative { 0i64.checked_sub_unsigned(value) } else { Some(value as i64) }
+            }
+
+            #parse_operand
+        })
+    }
+
+    /// Generates `print_asm`, which prints x86 instructions in intel (GAS/MASM
+    /// or NASM flavoured) or AT&T syntax
+    ///
+    /// The size keyword (`qword ptr`, `qword` for NASM) and suffix (`q`) come from the first form
+    /// which takes the operands. Requires `Display` on `Reg`
+    fn gen_x86_printer(&self, paths: &Paths) -> TokenStream {
+        let Paths { asm, vis, .. } = paths;
+
+        if self.insts.is_empty() {
+            return TokenStream::new();
+        }
+
+        let mut arms = Vec::new();
+
+        for inst in &self.insts {
+            let variant = format_ident!("{}", variant_name(&inst.name));
+
+            for form in &inst.forms {
+                let Some(size) = int_size(form) else { continue };
+
+                let count = Literal::usize_unsuffixed(form.operands.len());
+                let mut conds = vec![quote!(ops.len() == #count)];
+                conds.extend((0..form.operands.len()).map(|index| spec_condition(form, index)));
+                let cond = all(&conds);
+
+                let size = Literal::u32_unsuffixed(size);
+                arms.push(quote!(Mnemonic::#variant if #cond => Some(#size),));
             }
-            builder.push_str(")");
         }
 
-        amount += 1;
+        quote! {
+            /// The assembly syntax `print_asm` writes
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+            #vis enum Syntax {
+                /// `add qword ptr [rbx + rcx*4 + 8], 1`, the intel syntax of GAS and MASM
+                #[default]
+                Intel,
+                /// `add qword [rbx + rcx*4 + 8], 1`, the intel syntax of NASM
+                Nasm,
+                /// `addq $1, 8(%rbx,%rcx,4)`
+                Att,
+            }
+
+            /// Prints `asm` in the syntax
+            #vis fn print_asm(asm: &#asm, syntax: Syntax) -> String {
+                let mnemonic = asm.mnemonic();
+                let ops = asm.operands();
+                let size = operand_size(mnemonic, ops);
+
+                let mut out = mnemonic.to_string();
+
+                let ops: Vec<String> = match syntax {
+                    Syntax::Intel | Syntax::Nasm => {
+                        // the size is only ambiguous if no register gives it
+                        let keyword = match size {
+                            _ if ops.iter().any(|op| matches!(op, Operand::Reg(_) | Operand::Tmp(_))) => "",
+                            Some(8) => "byte",
+                            Some(16) => "word",
+                            Some(32) => "dword",
+                            Some(64) => "qword",
+                            _ => "",
+                        };
+
+                        let keyword = match (keyword, syntax) {
+                            ("", _) => String::new(),
+                            (keyword, Syntax::Nasm) => format!("{} ", keyword),
+                            (keyword, _) => format!("{} ptr ", keyword),
+                        };
+
+                        ops.iter().map(|op| print_intel(op, &keyword)).collect()
+                    },
+                    Syntax::Att => {
+                        out.push_str(match size {
+                            Some(8) => "b",
+                            Some(16) => "w",
+                            Some(32) => "l",
+                            Some(64) => "q",
+                            _ => "",
+                        });
+
+                        ops.iter().rev().map(print_att).collect()
+                    },
+                };
+
+                if !ops.is_empty() {
+                    out.push(' ');
+                    out.push_str(&ops.join(", "));
+                }
+
+                out
+            }
+
+            /// The size in bits of the integer operands of the first form which takes the operands
+            #[allow(unused_variables)]
+            fn operand_size(mnemonic: Mnemonic, ops: &[Operand]) -> Option<u32> {
+                match mnemonic {
+                    #(#arms)*
+                    _ => None,
+                }
+            }
+
+            fn print_intel(op: &Operand, keyword: &str) -> String {
+                match op {
+                    Operand::Tmp(num) => format!("%t{}", num),
+                    Operand::Reg(reg) => reg.to_string(),
+                    Operand::Imm(imm) => imm.to_string(),
+                    Operand::Mem(mem) => {
+                        let mut terms = Vec::new();
+
+                        if let Some(base) = mem.base() {
+                            terms.push(base.to_string());
+                        }
+
+                        if let Some(index) = mem.index() {
+                            terms.push(match mem.scale() {
+                                0 | 1 => index.to_string(),
+                                scale => format!("{}*{}", index, scale),
+                            });
+                        }
+
+                        let mut out = format!("{}[{}", keyword, terms.join(" + "));
+
+                        match mem.displ() {
+                            0 if !terms.is_empty() => {},
+                            displ if terms.is_empty() => out.push_str(&displ.to_string()),
+                            displ if displ < 0 => out.push_str(&format!(" - {}", displ.unsigned_abs())),
+                            displ => out.push_str(&format!(" + {}", displ)),
+                        }
+
+                        out.push(']');
+                        out
+                    },
+                }
+            }
+
+            fn print_att(op: &Operand) -> String {
+                match op {
+                    Operand::Tmp(num) => format!("%t{}", num),
+                    Operand::Reg(reg) => format!("%{}", reg),
+                    Operand::Imm(imm) => format!("${}", imm),
+                    Operand::Mem(mem) => {
+                        let displ = match mem.displ() {
+                            0 if mem.base().is_some() || mem.index().is_some() => String::new(),
+                            displ => displ.to_string(),
+                        };
+
+                        let base = mem.base().map(|base| format!("%{}", base)).unwrap_or_default();
+
+                        match mem.index() {
+                            Some(index) => format!("{}({},%{},{})", displ, base, index, mem.scale().max(1)),
+                            None if mem.base().is_some() => format!("{}({})", displ, base),
+                            None => displ,
+                        }
+                    },
+                }
+            }
+        }
     }
-    
-    amount
-}
\ No newline at end of file
+
+    /// Generates `can_select` and `legal_types` for the legalizer
+    fn gen_legality(&self, paths: &Paths) -> Result<TokenStream, Error> {
+        let Paths { node, ir, vis, .. } = paths;
+
+        // grouped by opcode in order of first appearance
+        let mut opcodes: Vec<(&str, TokenStream, Vec<String>)> = Vec::new();
+
+        for pat in &self.patterns {
+            let index = match opcodes.iter().position(|(mnemonic, ..)| *mnemonic == pat.variant.mnemonic) {
+                Some(index) => index,
+                None => {
+                    opcodes.push((&pat.variant.mnemonic, opcode(pat)?, Vec::new()));
+                    opcodes.len() - 1
+                },
+            };
+
+            let types = &mut opcodes[index].2;
+
+            let accepted = match &pat.variant.ty {
+                // concrete types which are not in the list (e.g. vectors) are added
+                Some(ty) if !is_type_predicate(ty) => vec![ty.to_owned()],
+                ty => self.config.types.iter().filter(|candidate| accepts(ty.as_deref(), candidate)).cloned().collect(),
+            };
+
+            for ty in accepted {
+                if !types.contains(&ty) {
+                    types.push(ty);
+                }
+            }
+        }
+
+        let mut arms = Vec::new();
+
+        for (mnemonic, opcode, types) in &opcodes {
+            let pat = self.patterns.iter().find(|pat| pat.variant.mnemonic == *mnemonic).expect("opcodes come from patterns");
+            let types = types.iter().map(|ty| type_metadata(paths, pat, ty)).collect::<Result<Vec<_>, _>>()?;
+
+            arms.push(quote!(DagOpCode::#opcode => &[#(#types),*],));
+        }
+
+        Ok(quote! {
+            /// Whether a pattern compiles `node`
+            #vis fn can_select(node: &#node) -> bool {
+                select(node).is_some()
+            }
+
+            /// The types some pattern for `opcode` accepts (operand kinds are not considered)
+            #vis fn legal_types(opcode: DagOpCode) -> &'static [#ir::TypeMetadata] {
+                match opcode {
+                    #(#arms)*
+                    _ => &[],
+                }
+            }
+        })
+    }
+
+    /// Generates `uses` and `defs`, the implicitly read and written registers and flags
+    fn gen_implicit(&self, paths: &Paths) -> TokenStream {
+        let Paths { node, reg, vis, .. } = paths;
+
+        let query = |regs: fn(&ast::Pattern) -> &Vec<String>| {
+            let arms = self.patterns.iter().enumerate()
+                .filter(|(_, pat)| !regs(pat).is_empty())
+                .map(|(id, pat)| {
+                    let id = Literal::usize_unsuffixed(id);
+                    let marker = marker(pat);
+
+                    let regs = regs(pat).iter().map(|name| match name.as_str() {
+                        "flags" => quote!(Implicit::Flags),
+                        name => {
+                            let name = format_ident!("{}", name);
+                            quote!(Implicit::Reg(#reg::#name()))
+                        },
+                    });
+
+                    quote! {
+                        #marker
+                        Some(#id) => vec![#(#regs),*],
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            quote! {
+                match select_with(node, goal) {
+                    #(#arms)*
+                    _ => Vec::new(),
+                }
+            }
+        };
+
+        let uses = query(|pat| &pat.uses);
+        let defs = query(|pat| &pat.defs);
+
+        quote! {
+            /// A register or the status flags an instruction accesses without naming them
+            #[derive(Debug, Clone)]
+            #vis enum Implicit {
+                Reg(#reg),
+                Flags,
+            }
+
+            /// What the instructions for `node` implicitly read
+            #vis fn uses(node: &#node) -> Vec<Implicit> {
+                uses_with(node, CostGoal::default())
+            }
+
+            /// What the pattern `compile_with` uses for `goal` implicitly reads
+            #vis fn uses_with(node: &#node, goal: CostGoal) -> Vec<Implicit> {
+                #uses
+            }
+
+            /// What the instructions for `node` implicitly write
+            #vis fn defs(node: &#node) -> Vec<Implicit> {
+                defs_with(node, CostGoal::default())
+            }
+
+            /// What the pattern `compile_with` uses for `goal` implicitly writes
+            #vis fn defs_with(node: &#node, goal: CostGoal) -> Vec<Implicit> {
+                #defs
+            }
+        }
+    }
+
+    /// Generates `PATTERNS`, the static description of every pattern
+    fn gen_info(&self, paths: &Paths) -> Result<TokenStream, Error> {
+        let Paths { vis, .. } = paths;
+
+        let mut infos = Vec::new();
+
+        for pat in &self.patterns {
+            let variant = &pat.variant;
+
+            let mnemonic = &variant.mnemonic;
+            let operands = [variant.ls, variant.rs, variant.op3].into_iter().flatten().map(operand_kind);
+            let out = option(variant.out.map(operand_kind));
+            let ty = option(variant.ty.as_ref());
+
+            // the operands are materialized into 0xF0.. just like in `tmps`
+            let mut tmps = leaves(pat).into_iter()
+                .map(|leaf| {
+                    let tmp = operand_tmp(leaf.tmp);
+                    let kind = operand_kind(leaf.kind);
+                    quote!(Temporary { num: #tmp, kind: #kind, ty: None, reg: None })
+                })
+                .collect::<Vec<_>>();
+
+            for tmp in &pat.maps {
+                let num = Literal::usize_unsuffixed(tmp_number(pat, tmp)?);
+                let kind = operand_kind(tmp.ty);
+                let ty = option(tmp.size.as_ref());
+                let reg = option(tmp.reg.as_ref());
+                tmps.push(quote!(Temporary { num: #num, kind: #kind, ty: #ty, reg: #reg }));
+            }
+
+            if let Some(kind) = tie_tmp(pat) {
+                let kind = operand_kind(kind);
+                tmps.push(quote!(Temporary { num: TIE_TMP, kind: #kind, ty: None, reg: None }));
+            }
+
+            let overwrittes = &pat.overwrittes;
+            let uses = &pat.uses;
+            let defs = &pat.defs;
+            let hook = option(pat.hook.as_ref());
+            let file = &pat.file;
+            let line = Literal::usize_unsuffixed(pat.loc.line);
+            let col = Literal::usize_unsuffixed(pat.loc.col);
+
+            let asm_lines = pat.lines.iter().filter(|line| matches!(line, AsmLine::Asm(..))).count() as u32;
+            let instrs = Literal::u32_unsuffixed(pat.cost.instrs.unwrap_or(asm_lines));
+            let latency = Literal::u32_unsuffixed(pat.cost.latency.unwrap_or(asm_lines));
+            let size = Literal::u32_unsuffixed(pat.cost.size.unwrap_or(asm_lines));
+
+            infos.push(quote! {
+                PatternInfo {
+                    mnemonic: #mnemonic,
+                    operands: &[#(#operands),*],
+                    out: #out,
+                    ty: #ty,
+                    tmps: &[#(#tmps),*],
+                    overwrittes: &[#(#overwrittes),*],
+                    uses: &[#(#uses),*],
+                    defs: &[#(#defs),*],
+                    hook: #hook,
+                    cost: Cost { instrs: #instrs, latency: #latency, size: #size },
+                    file: #file,
+                    line: #line,
+                    col: #col,
+                }
+            });
+        }
+
+        Ok(quote! {
+            /// The kind of an operand, output or temporary
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            #vis enum OperandKind {
+                Gr,
+                Fp,
+                Imm,
+                Mem,
+                Any,
+            }
+
+            /// Describes a pattern of the .td files
+            #[derive(Debug, Clone, Copy)]
+            #vis struct PatternInfo {
+                pub mnemonic: &'static str,
+                pub operands: &'static [OperandKind],
+                pub out: Option<OperandKind>,
+                /// the type constraint as written in the .td file
+                pub ty: Option<&'static str>,
+                pub tmps: &'static [Temporary],
+                pub overwrittes: &'static [&'static str],
+                /// the implicitly read registers (`flags` for the status flags)
+                pub uses: &'static [&'static str],
+                /// the implicitly written registers (`flags` for the status flags)
+                pub defs: &'static [&'static str],
+                pub hook: Option<&'static str>,
+                pub cost: Cost,
+                pub file: &'static str,
+                pub line: usize,
+                pub col: usize,
+            }
+
+            /// A temporary of a pattern
+            #[derive(Debug, Clone, Copy)]
+            #vis struct Temporary {
+                pub num: usize,
+                /// `Any` for temporaries pinned to a register
+                pub kind: OperandKind,
+                /// the type if it differs from the node
+                pub ty: Option<&'static str>,
+                /// the register the temporary is pinned to
+                pub reg: Option<&'static str>,
+            }
+
+            /// The costs of a pattern, undeclared ones are the number of asm lines
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            #vis struct Cost {
+                pub instrs: u32,
+                pub latency: u32,
+                pub size: u32,
+            }
+
+            impl Cost {
+                #vis fn get(&self, goal: CostGoal) -> u32 {
+                    match goal {
+                        CostGoal::Instrs => self.instrs,
+                        CostGoal::Latency => self.latency,
+                        CostGoal::Size => self.size,
+                    }
+                }
+            }
+
+            /// All patterns, indexed by `PatternId`
+            #vis static PATTERNS: &[PatternInfo] = &[#(#infos),*];
+        })
+    }
+}
+
+/// The table of an encoding which is passed to the generated encoder and decoder
+fn encoding_table(encoding: &ast::Encoding, form: &ast::InstForm, byte_regs_field: bool) -> TokenStream {
+    let specs: Vec<ast::OperandSpec> = form.operands.iter().map(|op| ast::OperandSpec::of(op)).collect();
+
+    match encoding {
+        ast::Encoding::X86(enc) => {
+            let position = |pred: fn(&ast::OperandSpec) -> bool| specs.iter().position(pred).map(Literal::usize_unsuffixed);
+
+            let reg = position(|spec| *spec == ast::OperandSpec::Reg);
+            let rm = position(|spec| matches!(spec, ast::OperandSpec::RegOrMem | ast::OperandSpec::Mem));
+            let imm_op = position(|spec| matches!(spec, ast::OperandSpec::Imm(_) | ast::OperandSpec::UImm(_)));
+
+            let prefixes = enc.prefixes.iter().map(|byte| hex(*byte as u64));
+            let rex_w = enc.rex_w;
+            let opcode = enc.opcode.iter().map(|byte| hex(*byte as u64));
+            let plus_reg = option(if enc.plus_reg { reg.clone() } else { None });
+            let modrm = option(match enc.modrm {
+                Some(ast::ModRm::Reg) => Some(quote!((ModRmReg::Operand(#reg), #rm))),
+                Some(ast::ModRm::Digit(digit)) => {
+                    let digit = Literal::u8_unsuffixed(digit);
+                    Some(quote!((ModRmReg::Digit(#digit), #rm)))
+                },
+                None => None,
+            });
+            let imm = option(enc.imm.map(|size| {
+                let size = Literal::usize_unsuffixed(size);
+                quote!((#imm_op, #size))
+            }));
+
+            let byte_regs = if byte_regs_field {
+                let indices = byte_regs(form).into_iter().map(Literal::usize_unsuffixed);
+                quote!(byte_regs: &[#(#indices),*],)
+            } else {
+                TokenStream::new()
+            };
+
+            quote! {
+                &X86Encoding {
+                    prefixes: &[#(#prefixes),*],
+                    rex_w: #rex_w,
+                    opcode: &[#(#opcode),*],
+                    plus_reg: #plus_reg,
+                    modrm: #modrm,
+                    imm: #imm,
+                    #byte_regs
+                }
+            }
+        },
+        ast::Encoding::Bits(fields) => {
+            let fields = fields.iter().map(|field| {
+                let value = match field.value {
+                    ast::BitValue::Const(value) => {
+                        let value = hex(value);
+                        quote!(BitValue::Const(#value))
+                    },
+                    ast::BitValue::Operand(index) => {
+                        let index = Literal::usize_unsuffixed(index);
+                        quote!(BitValue::Operand(#index))
+                    },
+                    ast::BitValue::Base(index) => {
+                        let index = Literal::usize_unsuffixed(index);
+                        quote!(BitValue::Base(#index))
+                    },
+                    ast::BitValue::Offset(index) => {
+                        let index = Literal::usize_unsuffixed(index);
+                        quote!(BitValue::Offset(#index))
+                    },
+                };
+                let (hi, lo) = (Literal::u32_unsuffixed(field.hi), Literal::u32_unsuffixed(field.lo));
+                quote!((#value, #hi, #lo))
+            });
+
+            quote!(&[#(#fields),*])
+        },
+    }
+}
+
+/// The operands of the form which are 8 bit registers (`r8`, `r/m8`)
+fn byte_regs(form: &ast::InstForm) -> Vec<usize> {
+    form.operands.iter().enumerate()
+        .filter(|(_, op)| matches!(op.as_str(), "r8" | "r/m8"))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// The decoder for byte oriented x86 encodings
+fn gen_decode_x86(paths: &Paths, byte_regs: bool) -> TokenStream {
+    let Paths { reg, .. } = paths;
+
+    // without a rex prefix, the 8 bit registers 4 to 7 are ah, ch, dh and bh
+    let reg_of = if byte_regs {
+        quote! {
+            let has_rex = bytes.get(enc.prefixes.len()).is_some_and(|byte| byte & 0xF0 == 0x40);
+
+            let reg_of = |num: u8, index: usize| if !has_rex && enc.byte_regs.contains(&index) && (4..8).contains(&num) {
+                #reg::high_byte(num)
+            } else {
+                #reg::from_enc(num, size(index))
+            };
+        }
+    } else {
+        quote!(let reg_of = |num: u8, index: usize| #reg::from_enc(num, size(index));)
+    };
+
+    quote! {
+        fn decode_x86(enc: &X86Encoding, specs: &[OperandSpec], bytes: &[u8]) -> Option<(Vec<Operand>, usize)> {
+            let mut pos = enc.prefixes.len();
+
+            if !bytes.starts_with(enc.prefixes) {
+                return None;
+            }
+
+            // W, R, X, B
+            let mut rex = 0;
+
+            if let Some(byte) = bytes.get(pos).filter(|byte| *byte & 0xF0 == 0x40) {
+                rex = byte & 0x0F;
+                pos += 1;
+            }
+
+            if (rex & 0b1000 != 0) != enc.rex_w {
+                return None;
+            }
+
+            let (last, opcode) = enc.opcode.split_last()?;
+
+            if !bytes[pos..].starts_with(opcode) {
+                return None;
+            }
+
+            pos += opcode.len();
+
+            let size = |index: usize| match specs[index] {
+                OperandSpec::Reg(size) | OperandSpec::RegOrMem(size) => size,
+                _ => 0,
+            };
+
+            #reg_of
+
+            let mut ops: Vec<Option<Operand>> = vec![None; specs.len()];
+
+            let byte = *bytes.get(pos)?;
+            pos += 1;
+
+            match enc.plus_reg {
+                Some(index) if byte & !7 == *last => ops[index] = Some(Operand::Reg(reg_of(byte & 7 | (rex & 0b0001) << 3, index))),
+                None if byte == *last => {},
+                _ => return None,
+            }
+
+            if let Some((reg, rm)) = &enc.modrm {
+                let modrm = *bytes.get(pos)?;
+                pos += 1;
+
+                let md = modrm >> 6;
+                let reg_field = modrm >> 3 & 7;
+                let rm_field = modrm & 7;
+
+                match reg {
+                    ModRmReg::Operand(index) => ops[*index] = Some(Operand::Reg(reg_of(reg_field | (rex & 0b0100) << 1, *index))),
+                    ModRmReg::Digit(digit) if reg_field == *digit => {},
+                    ModRmReg::Digit(_) => return None,
+                }
+
+                ops[*rm] = Some(if md == 0b11 {
+                    if matches!(specs[*rm], OperandSpec::Mem) {
+                        return None;
+                    }
+
+                    Operand::Reg(reg_of(rm_field | (rex & 0b0001) << 3, *rm))
+                } else {
+                    let (mem, len) = decode_mem(md, rm_field, rex, &bytes[pos..])?;
+                    pos += len;
+                    Operand::Mem(mem)
+                });
+            }
+
+            if let Some((index, size)) = enc.imm {
+                let mut imm = [0; 8];
+                imm[..size].copy_from_slice(bytes.get(pos..pos + size)?);
+                pos += size;
+
+                // sign extended unless the operand is unsigned
+                let imm = i64::from_le_bytes(imm);
+                let shift = 64 - size as u32 * 8;
+
+                ops[index] = Some(Operand::Imm(match specs[index] {
+                    OperandSpec::UImm(_) => imm,
+                    _ => imm << shift >> shift,
+                }));
+            }
+
+            Some((ops.into_iter().collect::<Option<Vec<_>>>()?, pos))
+        }
+
+        /// Decodes the memory operand after the modrm byte, returns it with the length of the sib byte and displacement
+        fn decode_mem(md: u8, rm: u8, rex: u8, bytes: &[u8]) -> Option<(MemoryDispl, usize)> {
+            let mut pos = 0;
+
+            let (base, index, scale) = if rm == 0b100 {
+                let sib = *bytes.first()?;
+                pos += 1;
+
+                let index = sib >> 3 & 7 | (rex & 0b0010) << 2;
+                let index = if index == 0b100 { None } else { Some(#reg::from_enc(index, 64)) };
+
+                let base = sib & 7;
+                let base = if base == 0b101 && md == 0b00 { None } else { Some(#reg::from_enc(base | (rex & 0b0001) << 3, 64)) };
+
+                (base, index, 1 << (sib >> 6))
+            } else if rm == 0b101 && md == 0b00 {
+                // rip relative addressing is not encoded by `encode`
+                return None;
+            } else {
+                (Some(#reg::from_enc(rm | (rex & 0b0001) << 3, 64)), None, 1)
+            };
+
+            let displ = match md {
+                0b01 => {
+                    pos += 1;
+                    *bytes.get(pos - 1)? as i8 as i32
+                },
+                0b10 => {
+                    pos += 4;
+                    i32::from_le_bytes(bytes.get(pos - 4..pos)?.try_into().ok()?)
+                },
+                _ if base.is_none() => {
+                    pos += 4;
+                    i32::from_le_bytes(bytes.get(pos - 4..pos)?.try_into().ok()?)
+                },
+                _ => 0,
+            };
+
+            Some((MemoryDispl::from_parts(base, index, scale, displ), pos))
+        }
+    }
+}
+
+/// The decoder for fixed width bit field encodings
+fn gen_decode_bits(paths: &Paths) -> TokenStream {
+    let Paths { reg, .. } = paths;
+
+    quote! {
+        fn decode_bits(fields: &[(BitValue, u32, u32)], specs: &[OperandSpec], bytes: &[u8]) -> Option<(Vec<Operand>, usize)> {
+            let width: u32 = fields.iter().map(|(_, hi, lo)| hi - lo + 1).sum();
+            let len = width as usize / 8;
+
+            let mut word = [0; 8];
+            word[..len].copy_from_slice(bytes.get(..len)?);
+            let word = u64::from_le_bytes(word);
+
+            // the bits of every operand and how many of them are used
+            let mut values = vec![0u64; specs.len()];
+            let mut widths = vec![0u32; specs.len()];
+            let mut bases = vec![None; specs.len()];
+
+            let mut shift = width;
+
+            for (value, hi, lo) in fields {
+                let bits = hi - lo + 1;
+                shift -= bits;
+
+                let field = word >> shift & u64::MAX >> (64 - bits);
+
+                match *value {
+                    BitValue::Const(value) if field != value => return None,
+                    BitValue::Const(_) => {},
+                    BitValue::Operand(index) | BitValue::Offset(index) => {
+                        values[index] |= field << lo;
+                        widths[index] = widths[index].max(hi + 1);
+                    },
+                    BitValue::Base(index) => bases[index] = Some(field as u8),
+                }
+            }
+
+            let sign_extend = |value: u64, width: u32| match width {
+                1..=63 => (value << (64 - width)) as i64 >> (64 - width),
+                _ => value as i64,
+            };
+
+            let mut ops = Vec::new();
+
+            for (index, spec) in specs.iter().enumerate() {
+                ops.push(match *spec {
+                    OperandSpec::Reg(size) | OperandSpec::RegOrMem(size) => Operand::Reg(#reg::from_enc(values[index] as u8, size)),
+                    OperandSpec::Imm(width) => Operand::Imm(sign_extend(values[index], width.min(widths[index]))),
+                    OperandSpec::UImm(_) => Operand::Imm(values[index] as i64),
+                    OperandSpec::Mem => {
+                        let base = #reg::from_enc(bases[index]?, 0);
+                        Operand::Mem(MemoryDispl::from_parts(Some(base), None, 1, sign_extend(values[index], widths[index]) as i32))
+                    },
+                });
+            }
+
+            Some((ops, len))
+        }
+    }
+}
+
+/// The parser for a single operand in the assembly syntax of the target
+fn gen_parse_operand(target: ast::AstTarget) -> TokenStream {
+    match target {
+        ast::AstTarget::X86 => quote! {
+            /// Parses an operand in intel syntax (`rax`, `-8`, `qword ptr [rbx + rcx*4 + 8]`)
+            fn parse_operand(text: &str) -> Option<Operand> {
+                let Some(start) = text.find('[') else {
+                    return match parse_imm(text) {
+                        Some(imm) => Some(Operand::Imm(imm)),
+                        None => text.parse().ok().map(Operand::Reg),
+                    };
+                };
+
+                // the size is checked against the form
+                if !text[..start].trim().is_empty() && ptr_size(text).is_none() {
+                    return None;
+                }
+
+                let mem = text[start + 1..].strip_suffix(']')?;
+
+                let (mut base, mut index, mut scale, mut displ) = (None, None, 1, 0i64);
+
+                // the terms with the sign in front of them
+                let mut terms = Vec::new();
+                let mut term_start = 0;
+
+                for (pos, c) in mem.char_indices() {
+                    if matches!(c, '+' | '-') {
+                        terms.push(&mem[term_start..pos]);
+                        term_start = pos;
+                    }
+                }
+
+                terms.push(&mem[term_start..]);
+
+                for (pos, term) in terms.into_iter().enumerate() {
+                    let term = term.trim();
+
+                    let (negative, term) = match term.strip_prefix('-') {
+                        Some(term) => (true, term.trim_start()),
+                        None => (false, term.strip_prefix('+').unwrap_or(term).trim_start()),
+                    };
+
+                    if term.is_empty() {
+                        // `[-8]`
+                        if pos == 0 { continue; }
+                        return None;
+                    }
+
+                    if let Some(imm) = parse_imm(term) {
+                        displ = displ.checked_add(if negative { imm.checked_neg()? } else { imm })?;
+                        continue;
+                    }
+
+                    if negative || index.is_some() {
+                        return None;
+                    }
+
+                    match term.split_once('*') {
+                        Some((reg, factor)) => {
+                            // `rcx*4` or `4*rcx`
+                            let (reg, factor) = if parse_imm(reg.trim()).is_some() { (factor, reg) } else { (reg, factor) };
+
+                            index = Some(reg.trim().parse().ok()?);
+                            scale = match parse_imm(factor.trim())? {
+                                factor @ (1 | 2 | 4 | 8) => factor as u8,
+                                _ => return None,
+                            };
+                        },
+                        None if base.is_none() => base = Some(term.parse().ok()?),
+                        None => index = Some(term.parse().ok()?),
+                    }
+                }
+
+                Some(Operand::Mem(MemoryDispl::from_parts(base, index, scale, i32::try_from(displ).ok()?)))
+            }
+
+            /// The size in bits of a memory operand (`qword ptr [rbx]` is 64), None without one
+            fn ptr_size(text: &str) -> Option<u32> {
+                let size = text[..text.find('[')?].trim().to_ascii_lowercase();
+
+                match size.strip_suffix("ptr").map_or(size.as_str(), str::trim_end) {
+                    "byte" => Some(8),
+                    "word" => Some(16),
+                    "dword" => Some(32),
+                    "qword" => Some(64),
+                    "xmmword" => Some(128),
+                    _ => None,
+                }
+            }
+        },
+        // only the operands `from_parts` builds, the pre and post indexed, shifted and extended
+        // operands of patterns (`MemoryDispl::pre_index`, `Operand::shifted`, ...) are rejected
+        ast::AstTarget::AArch64 => quote! {
+            /// Parses an operand in aarch64 syntax (`x1`, `#-8`, `[x0, #8]`, `[x0, x1, lsl #3]`)
+            fn parse_operand(text: &str) -> Option<Operand> {
+                if let Some(imm) = text.strip_prefix('#') {
+                    return parse_imm(imm.trim()).map(Operand::Imm);
+                }
+
+                let Some(mem) = text.strip_prefix('[') else {
+                    return text.parse().ok().map(Operand::Reg);
+                };
+
+                let parts = mem.strip_suffix(']')?.split(',').map(str::trim).collect::<Vec<_>>();
+                let base = Some(parts[0].parse().ok()?);
+
+                let mem = match parts[1..] {
+                    [] => MemoryDispl::from_parts(base, None, 1, 0),
+                    [offset] if offset.starts_with('#') => MemoryDispl::from_parts(base, None, 1, i32::try_from(parse_imm(offset[1..].trim())?).ok()?),
+                    [index] => MemoryDispl::from_parts(base, Some(index.parse().ok()?), 1, 0),
+                    [index, shift] => {
+                        let amount = shift.strip_prefix("lsl")?.trim_start().strip_prefix('#')?;
+
+                        let scale = match parse_imm(amount.trim())? {
+                            amount @ 0..=4 => 1 << amount,
+                            _ => return None,
+                        };
+
+                        MemoryDispl::from_parts(base, Some(index.parse().ok()?), scale, 0)
+                    },
+                    _ => return None,
+                };
+
+                Some(Operand::Mem(mem))
+            }
+        },
+        // relocations (`%lo(sym)(sp)`) have no runtime `Operand` and are rejected
+        ast::AstTarget::RiscV => quote! {
+            /// Parses an operand in risc-v syntax (`a0`, `-8`, `8(sp)`)
+            fn parse_operand(text: &str) -> Option<Operand> {
+                if let Some(imm) = parse_imm(text) {
+                    return Some(Operand::Imm(imm));
+                }
+
+                let Some((offset, base)) = text.strip_suffix(')').and_then(|text| text.split_once('(')) else {
+                    return text.parse().ok().map(Operand::Reg);
+                };
+
+                let displ = match offset.trim() {
+                    "" => 0,
+                    offset => i32::try_from(parse_imm(offset)?).ok()?,
+                };
+
+                Some(Operand::Mem(MemoryDispl::from_parts(Some(base.trim().parse().ok()?), None, 1, displ)))
+            }
+        },
+    }
+}
+
+/// The size of the integer operands of an x86 form (`r/m64` -> 64), None if
+/// it has none or they are no general purpose registers (`xmm/m64`)
+fn int_size(form: &ast::InstForm) -> Option<u32> {
+    let sized = form.operands.iter().find(|op| !matches!(ast::OperandSpec::of(op), ast::OperandSpec::Imm(_) | ast::OperandSpec::UImm(_)))?;
+    let digits = sized.strip_prefix("r/m").or_else(|| sized.strip_prefix('r')).or_else(|| sized.strip_prefix('m'))?;
+
+    digits.parse().ok()
+}
+
+/// Checks if the size written in front of memory operand `index` (`byte ptr`)
+/// is the size of the operand of the x86 form, None if the form does not give one
+fn size_condition(form: &ast::InstForm, index: usize) -> Option<TokenStream> {
+    let spec = &form.operands[index];
+
+    if !matches!(ast::OperandSpec::of(spec), ast::OperandSpec::Mem | ast::OperandSpec::RegOrMem) {
+        return None;
+    }
+
+    let size = Literal::u32_unsuffixed(spec[spec.trim_end_matches(|c: char| c.is_ascii_digit()).len()..].parse().ok()?);
+    let index = Literal::usize_unsuffixed(index);
+
+    Some(quote!(sizes[#index].map_or(true, |size| size == #size)))
+}
+
+/// Checks if operand `index` fits the operand of the instruction form
+fn spec_condition(form: &ast::InstForm, index: usize) -> TokenStream {
+    let spec = ast::OperandSpec::of(&form.operands[index]);
+    let index = Literal::usize_unsuffixed(index);
+
+    match spec {
+        ast::OperandSpec::Reg => quote!(matches!(ops[#index], Operand::Reg(_))),
+        ast::OperandSpec::Mem => quote!(matches!(ops[#index], Operand::Mem(_))),
+        ast::OperandSpec::RegOrMem => quote!(matches!(ops[#index], Operand::Reg(_) | Operand::Mem(_))),
+        ast::OperandSpec::Imm(_) | ast::OperandSpec::UImm(_) => match spec.imm_range(form.size()) {
+            Some((min, max)) => quote!(matches!(ops[#index], Operand::Imm(imm) if (#min..=#max).contains(&imm))),
+            None => quote!(matches!(ops[#index], Operand::Imm(_))),
+        },
+    }
+}
+
+/// The encoder for byte oriented x86 encodings
+fn gen_encode_x86(byte_regs: bool) -> TokenStream {
+    let (field, needs_rex) = if byte_regs {
+        let field = quote! {
+            /// the 8 bit register operands
+            byte_regs: &'static [usize],
+        };
+
+        // spl, bpl, sil and dil need a rex prefix, with one ah, ch, dh and bh can't be encoded
+        let needs_rex = quote! {
+            let mut high_byte = false;
+            let mut needs_rex = rex != 0;
+
+            for index in enc.byte_regs {
+                match &ops[*index] {
+                    Operand::Reg(reg) if reg.is_high_byte() => high_byte = true,
+                    Operand::Reg(reg) if (4..8).contains(&reg.enc()) => needs_rex = true,
+                    _ => {},
+                }
+            }
+
+            if high_byte && needs_rex {
+                return Err(());
+            }
+        };
+
+        (field, needs_rex)
+    } else {
+        (TokenStream::new(), quote!(let needs_rex = rex != 0;))
+    };
+
+    quote! {
+        /// How an x86 instruction form is encoded, operands are indices into the operands of the asm
+        struct X86Encoding {
+            prefixes: &'static [u8],
+            rex_w: bool,
+            opcode: &'static [u8],
+            /// the register added to the last opcode byte
+            plus_reg: Option<usize>,
+            /// the reg field and the r/m operand of the modrm byte
+            modrm: Option<(ModRmReg, usize)>,
+            /// the immediate and its size in bytes
+            imm: Option<(usize, usize)>,
+            #field
+        }
+
+        enum ModRmReg {
+            Operand(usize),
+            Digit(u8),
+        }
+
+        fn encode_x86(enc: &X86Encoding, ops: &[Operand], out: &mut Vec<u8>) -> Result<(), ()> {
+            let reg = |index: usize| match &ops[index] {
+                Operand::Reg(reg) => Ok(reg.enc()),
+                _ => Err(()),
+            };
+
+            // W, R, X, B
+            let mut rex = if enc.rex_w { 0b1000 } else { 0 };
+
+            let reg_field = match &enc.modrm {
+                Some((ModRmReg::Operand(index), _)) => reg(*index)?,
+                Some((ModRmReg::Digit(digit), _)) => *digit,
+                None => 0,
+            };
+
+            if reg_field & 8 != 0 {
+                rex |= 0b0100;
+            }
+
+            let plus = match enc.plus_reg {
+                Some(index) => reg(index)?,
+                None => 0,
+            };
+
+            if plus & 8 != 0 {
+                rex |= 0b0001;
+            }
+
+            let rm = enc.modrm.as_ref().map(|(_, index)| &ops[*index]);
+
+            match rm {
+                Some(Operand::Reg(reg)) if reg.enc() & 8 != 0 => rex |= 0b0001,
+                Some(Operand::Mem(mem)) => {
+                    if mem.base().map_or(false, |base| base.enc() & 8 != 0) {
+                        rex |= 0b0001;
+                    }
+
+                    if mem.index().map_or(false, |index| index.enc() & 8 != 0) {
+                        rex |= 0b0010;
+                    }
+                },
+                Some(Operand::Reg(_)) | None => {},
+                Some(_) => return Err(()),
+            }
+
+            #needs_rex
+
+            out.extend_from_slice(enc.prefixes);
+
+            if needs_rex {
+                out.push(0x40 | rex);
+            }
+
+            let (last, opcode) = enc.opcode.split_last().ok_or(())?;
+            out.extend_from_slice(opcode);
+            out.push(last + (plus & 7));
+
+            if let Some(rm) = rm {
+                encode_modrm(reg_field & 7, rm, out)?;
+            }
+
+            if let Some((index, size)) = enc.imm {
+                let Operand::Imm(imm) = &ops[index] else { return Err(()) };
+                out.extend_from_slice(&imm.to_le_bytes()[..size]);
+            }
+
+            Ok(())
+        }
+
+        fn encode_modrm(reg: u8, rm: &Operand, out: &mut Vec<u8>) -> Result<(), ()> {
+            let mem = match rm {
+                Operand::Reg(rm) => {
+                    out.push(0b11 << 6 | reg << 3 | rm.enc() & 7);
+                    return Ok(());
+                },
+                Operand::Mem(mem) => mem,
+                _ => return Err(()),
+            };
+
+            let scale = match mem.scale() {
+                0 | 1 => 0,
+                2 => 1,
+                4 => 2,
+                8 => 3,
+                _ => return Err(()),
+            };
+
+            // 0b100 in the index field of the sib byte means no index, so rsp can't be one
+            if mem.index().map_or(false, |index| index.enc() == 0b100) {
+                return Err(());
+            }
+
+            let index = mem.index().map_or(0b100, |index| index.enc() & 7);
+            let displ = mem.displ();
+
+            let Some(base) = mem.base() else {
+                // [index * scale + displ32]
+                out.push(reg << 3 | 0b100);
+                out.push(scale << 6 | index << 3 | 0b101);
+                out.extend_from_slice(&displ.to_le_bytes());
+                return Ok(());
+            };
+
+            let base = base.enc() & 7;
+
+            // rbp/r13 as a base always need a displacement
+            let md = if displ == 0 && base != 0b101 {
+                0b00
+            } else if i8::try_from(displ).is_ok() {
+                0b01
+            } else {
+                0b10
+            };
+
+            // rsp/r12 as a base always need a sib byte
+            if mem.index().is_some() || base == 0b100 {
+                out.push(md << 6 | reg << 3 | 0b100);
+                out.push(scale << 6 | index << 3 | base);
+            } else {
+                out.push(md << 6 | reg << 3 | base);
+            }
+
+            match md {
+                0b01 => out.push(displ as u8),
+                0b10 => out.extend_from_slice(&displ.to_le_bytes()),
+                _ => {},
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// The encoder for fixed width bit field encodings
+fn gen_encode_bits() -> TokenStream {
+    quote! {
+        #[derive(Clone, Copy)]
+        enum BitValue {
+            Const(u64),
+            Operand(usize),
+            Base(usize),
+            Offset(usize),
+        }
+
+        /// Packs the bits `hi..=lo` of the fields (most significant first) into a little endian word
+        fn encode_bits(fields: &[(BitValue, u32, u32)], ops: &[Operand], out: &mut Vec<u8>) -> Result<(), ()> {
+            let mut word: u64 = 0;
+            let mut width = 0;
+
+            for (value, hi, lo) in fields {
+                let value = match *value {
+                    BitValue::Const(value) => value << lo,
+                    BitValue::Operand(index) => match &ops[index] {
+                        Operand::Reg(reg) => reg.enc() as u64,
+                        Operand::Imm(imm) => *imm as u64,
+                        _ => return Err(()),
+                    },
+                    BitValue::Base(index) => match &ops[index] {
+                        Operand::Mem(mem) => mem.base().ok_or(())?.enc() as u64,
+                        _ => return Err(()),
+                    },
+                    BitValue::Offset(index) => match &ops[index] {
+                        Operand::Mem(mem) => mem.displ() as i64 as u64,
+                        _ => return Err(()),
+                    },
+                };
+
+                let bits = hi - lo + 1;
+                let mask = u64::MAX >> (64 - bits);
+
+                word = word.checked_shl(bits).unwrap_or(0) | (value >> lo) & mask;
+                width += bits;
+            }
+
+            out.extend_from_slice(&word.to_le_bytes()[..width as usize / 8]);
+
+            Ok(())
+        }
+    }
+}
+
+/// The error returned by the fallible `compile`
+fn gen_select_error(paths: &Paths) -> TokenStream {
+    let Paths { node, vis, .. } = paths;
+
+    quote! {
+        /// Why a node could not be compiled
+        #[derive(Debug)]
+        #vis enum SelectError {
+            /// there is no pattern for the opcode of the node
+            UnsupportedOpcode(#node),
+            /// no pattern for the opcode accepts the operands or type of the node
+            UnsupportedOperands(#node),
+            /// an operand of the node could not be turned into an asm operand
+            OperandMaterialization(#node),
+        }
+
+        impl std::fmt::Display for SelectError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    SelectError::UnsupportedOpcode(node) => write!(f, "unsupported opcode: {:?}", node.get_opcode()),
+                    SelectError::UnsupportedOperands(node) => write!(f, "not yet compilable variant: {} ({})", node, node.get_ty()),
+                    SelectError::OperandMaterialization(node) => write!(f, "failed to materialize the operands of: {}", node),
+                }
+            }
+        }
+
+        impl std::error::Error for SelectError {}
+    }
+}
+
+/// The runtime expansion of the risc-v `li` for operands which are not literals
+///
+/// Emits [`li_steps`] itself, so literals and runtime values are loaded with
+/// the same sequences. Requires `Reg::zero()`
+fn gen_expand_li(paths: &Paths) -> TokenStream {
+    let Paths { asm, reg, .. } = paths;
+    let li_steps = TokenStream::from_str(LI_STEPS).expect("li_steps is valid rust");
+
+    quote! {
+        /// Loads `imm` into `rd`, other operands than immediates (e.g. relocations) are added to zero
+        fn expand_li(rd: Operand, imm: Operand) -> Vec<#asm> {
+            let mut asm = Vec::new();
+
+            match imm {
+                Operand::Imm(value) => load_imm(&mut asm, rd, value),
+                imm => asm.push(#asm::with3(Mnemonic::Addi, rd, Operand::Reg(#reg::zero()), imm)),
+            }
+
+            asm
+        }
+
+        fn load_imm(asm: &mut Vec<#asm>, rd: Operand, value: i64) {
+            let mut steps = Vec::new();
+            li_steps(value, &mut steps);
+
+            for (index, (mnemonic, imm)) in steps.into_iter().enumerate() {
+                let src = if index == 0 { Operand::Reg(#reg::zero()) } else { rd.clone() };
+
+                asm.push(match mnemonic {
+                    "lui" => #asm::with2(Mnemonic::Lui, rd.clone(), Operand::Imm(imm)),
+                    "addiw" => #asm::with3(Mnemonic::Addiw, rd.clone(), src, Operand::Imm(imm)),
+                    "slli" => #asm::with3(Mnemonic::Slli, rd.clone(), src, Operand::Imm(imm)),
+                    _ => #asm::with3(Mnemonic::Addi, rd.clone(), src, Operand::Imm(imm)),
+                });
+            }
+        }
+
+        #li_steps
+    }
+}
+
+/// Defines the items and keeps their source in `LI_STEPS`, which
+/// [`gen_expand_li`] emits into the generated code
+macro_rules! with_source {
+    ($($item:tt)*) => {
+        $($item)*
+
+        const LI_STEPS: &str = stringify!($($item)*);
+    };
+}
+
+with_source! {
+    /// The shortest sequence of `addi`, `lui`, `addiw` and `slli` which loads `value`
+    ///
+    /// The first step reads `zero` (`lui` reads nothing), the others the
+    /// register being loaded
+    fn li_steps(value: i64, steps: &mut Vec<(&'static str, i64)>) {
+        let lo12 = value << 52 >> 52;
+
+        if (-2048..2048).contains(&value) {
+            steps.push(("addi", value));
+        } else if i32::try_from(value).is_ok() {
+            // lui sign extends the upper 20 bits, addiw wraps around to the 32 bit value
+            let hi20 = ((value + 0x800) >> 12) << 44 >> 44;
+            steps.push(("lui", hi20));
+
+            if lo12 != 0 {
+                steps.push(("addiw", lo12));
+            }
+        } else {
+            let hi52 = (value as u64).wrapping_add(0x800) >> 12;
+            let shift = 12 + hi52.trailing_zeros();
+            let hi = ((hi52 >> (shift - 12)) << shift) as i64 >> shift;
+
+            li_steps(hi, steps);
+            steps.push(("slli", shift as i64));
+
+            if lo12 != 0 {
+                steps.push(("addi", lo12));
+            }
+        }
+    }
+}
+
+/// Placeholder attribute which is turned into a `// ytbgen: file:line` comment by [`markers`]
+fn marker(pattern: &ast::Pattern) -> TokenStream {
+    let loc = format!("{}:{}", pattern.file, pattern.loc.line);
+    quote!(#[ytbgen = #loc])
+}
+
+/// Replaces the placeholder attributes of [`marker`] with comments
+///
+/// prettyplease drops comments, so they are inserted into the formatted code
+fn markers(code: &str) -> String {
+    let mut out = String::new();
+
+    for line in code.lines() {
+        let trimmed = line.trim_start();
+        let indent = &line[..line.len() - trimmed.len()];
+
+        let loc = trimmed.strip_prefix("#[ytbgen = ")
+            .and_then(|rest| rest.strip_suffix(']'))
+            .and_then(|lit| syn::parse_str::<syn::LitStr>(lit).ok());
+
+        match loc {
+            Some(loc) => out.push_str(&format!("{}{}{}\n", indent, MARKER, loc.value())),
+            None => out.push_str(&format!("{}\n", line)),
+        }
+    }
+
+    out
+}
+
+const MARKER: &str = "// ytbgen: ";
+
+/// Builds a JSON source map from the `// ytbgen: file:line` markers of the generated code
+///
+/// Every marker maps the (1-based, inclusive) line range of the match arm or
+/// statement following it to the pattern it was generated from
+pub fn source_map(code: &str) -> String {
+    let items = item_lines(code);
+
+    let mut mappings = Vec::new();
+
+    for (index, line) in code.lines().enumerate() {
+        let Some((file, pattern_line)) = line.trim_start().strip_prefix(MARKER).and_then(|loc| loc.rsplit_once(':')) else {
+            continue;
+        };
+        let Ok(pattern_line) = pattern_line.parse::<usize>() else { continue };
+
+        // the item starts on the line after its marker
+        let start = index + 2;
+        let Some(end) = items.get(&start) else { continue };
+
+        mappings.push(format!(
+            "    {{ \"start\": {}, \"end\": {}, \"file\": {}, \"line\": {} }}",
+            start, end, json_string(file), pattern_line,
+        ));
+    }
+
+    format!("{{\n  \"mappings\": [\n{}\n  ]\n}}\n", mappings.join(",\n"))
+}
+
+/// The last line of the outermost match arm or statement starting on a line,
+/// keyed by that line
+fn item_lines(code: &str) -> HashMap<usize, usize> {
+    struct Items(HashMap<usize, usize>);
+
+    impl Items {
+        fn add(&mut self, span: Span) {
+            let end = self.0.entry(span.start().line).or_default();
+            *end = (*end).max(span.end().line);
+        }
+    }
+
+    impl<'ast> Visit<'ast> for Items {
+        fn visit_arm(&mut self, arm: &'ast syn::Arm) {
+            self.add(arm.span());
+            visit::visit_arm(self, arm);
+        }
+
+        fn visit_stmt(&mut self, stmt: &'ast syn::Stmt) {
+            self.add(stmt.span());
+            visit::visit_stmt(self, stmt);
+        }
+    }
+
+    let mut items = Items(HashMap::new());
+
+    if let Ok(file) = syn::parse_file(code) {
+        items.visit_file(&file);
+    }
+
+    items.0
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+/// A property of a type which the type predicates test
+#[derive(Debug, Clone, Copy)]
+enum TypeProperty {
+    Int,
+    Signed,
+    Float,
+}
+
+/// The properties (and whether they hold) of the types `int`, `signed`,
+/// `unsigned`, `float` and `no_float` describe, None for concrete types
+///
+/// `select` tests them on the node and `legal_types` on the type names, so both agree
+fn type_predicate(ty: &str) -> Option<&'static [(TypeProperty, bool)]> {
+    Some(match ty {
+        "int" => &[(TypeProperty::Int, true)],
+        "signed" => &[(TypeProperty::Signed, true)],
+        "unsigned" => &[(TypeProperty::Int, true), (TypeProperty::Signed, false)],
+        "float" => &[(TypeProperty::Float, true)],
+        "no_float" => &[(TypeProperty::Float, false)],
+        _ => return None,
+    })
+}
+
+fn is_type_predicate(ty: &str) -> bool {
+    type_predicate(ty).is_some()
+}
+
+/// Whether the type constraint of a pattern accepts the scalar type
+///
+/// Properties are resolved by the type name: `iN` is signed, `uN` and `ptr`
+/// are unsigned integers and `fN` is a float
+fn accepts(constraint: Option<&str>, ty: &str) -> bool {
+    let number = |prefix: char| ty.strip_prefix(prefix).is_some_and(|bits| !bits.is_empty() && bits.chars().all(|c| c.is_ascii_digit()));
+
+    let Some(constraint) = constraint else { return true };
+
+    let Some(properties) = type_predicate(constraint) else {
+        return constraint == ty;
+    };
+
+    properties.iter().all(|(property, holds)| {
+        let has = match property {
+            TypeProperty::Int => number('i') || number('u') || ty == "ptr",
+            TypeProperty::Signed => number('i'),
+            TypeProperty::Float => number('f'),
+        };
+
+        has == *holds
+    })
+}
+
+/// The `TypeMetadata` for a type of the .td file (`i64`, `<4xi32>`)
+fn type_metadata(paths: &Paths, pattern: &ast::Pattern, ty: &str) -> Result<TokenStream, Error> {
+    let ir = &paths.ir;
+
+    if ty.contains('<') && ty.contains('>') {
+        // vector type
+
+        let ty = ty.replace(['<', '>'], "");
+
+        let Some((size, ty)) = ty.split_once('x') else {
+            return Err(pattern_error(pattern, pattern.loc, "expected size for vector types"));
+        };
+
+        let size = lex(pattern, pattern.loc, size)?;
+        let ty = lex(pattern, pattern.loc, ty)?;
+
+        Ok(quote!(#ir::TypeMetadata::Vector(#ir::VecTy { size: #size, ty: #ir::StdTypeMetadata::#ty })))
+    } else {
+        let ty = lex(pattern, pattern.loc, ty)?;
+        Ok(quote!(#ir::TypeMetadata::#ty))
+    }
+}
+
+/// The number of a mapped temporary (`%t1` -> 1)
+fn tmp_number(pattern: &ast::Pattern, map: &ast::Map) -> Result<usize, Error> {
+    map.var.replace("%t", "").parse::<usize>()
+        .map_err(|_| pattern_error(pattern, pattern.loc, format!("invalid temporary: {}", map.var)))
+}
+
+fn operand_kind(kind: ast::OpVariant) -> TokenStream {
+    match kind {
+        ast::OpVariant::Gr => quote!(OperandKind::Gr),
+        ast::OpVariant::Fp => quote!(OperandKind::Fp),
+        ast::OpVariant::Imm => quote!(OperandKind::Imm),
+        ast::OpVariant::Mem => quote!(OperandKind::Mem),
+        ast::OpVariant::Any => quote!(OperandKind::Any),
+    }
+}
+
+/// An unsuffixed hex literal (`0x0F`)
+fn hex(value: u64) -> Literal {
+    Literal::from_str(&format!("0x{:02X}", value)).expect("valid literal")
+}
+
+fn option<T: quote::ToTokens>(value: Option<T>) -> TokenStream {
+    match value {
+        Some(value) => quote!(Some(#value)),
+        None => quote!(None),
+    }
+}
+
+fn pattern_error(pattern: &ast::Pattern, loc: ast::Loc, message: impl ToString) -> Error {
+    Error {
+        file: Some(pattern.file.clone()),
+        loc: Some(loc),
+        pattern: Some(pattern.variant.to_string()),
+        message: message.to_string(),
+    }
+}
+
+fn lex(pattern: &ast::Pattern, loc: ast::Loc, code: &str) -> Result<TokenStream, Error> {
+    TokenStream::from_str(code).map_err(|_| pattern_error(pattern, loc, "invalid token or unbalanced delimiter"))
+}
+
+/// Maps a span of the lexed pattern body back to the .td file
+///
+/// Line `n` of the body is generated from the `n`th asm/rust line of the pattern
+fn locate(pattern: &ast::Pattern, span: Span) -> ast::Loc {
+    // call site spans (e.g. the end of the input) do not point into the body
+    if span.byte_range() == (0..0) {
+        return pattern.loc;
+    }
+
+    let start = span.start();
+
+    match start.line.checked_sub(1).and_then(|index| pattern.lines.get(index)) {
+        Some(AsmLine::Rust(_, loc)) => ast::Loc { line: loc.line, col: loc.col + start.column },
+        Some(AsmLine::Asm(_, loc)) => *loc,
+        None => pattern.loc,
+    }
+}
+
+fn opcode(pattern: &ast::Pattern) -> Result<TokenStream, Error> {
+    lex(pattern, pattern.loc, &pattern.variant.mnemonic)
+}
+
+/// Joins the conditions with `&&`
+fn all(conds: &[TokenStream]) -> TokenStream {
+    if conds.is_empty() {
+        quote!(true)
+    } else {
+        quote!(#(#conds)&&*)
+    }
+}
+
+/// The temporary an operand is materialized into if it needs instructions
+fn operand_tmp(index: usize) -> syn::LitInt {
+    syn::LitInt::new(&format!("0x{:X}", 0xF0 + index), Span::call_site())
+}
+
+/// Builds the body of a pattern out of its asm and rust lines and whether it needs `expand_li`
+///
+/// Every line becomes exactly one line of the lexed body, so errors can be mapped back
+fn construct_body(target: ast::AstTarget, paths: &Paths, pattern: &ast::Pattern) -> Result<(TokenStream, bool), Error> {
+    let leaves = leaves(pattern);
+    let kinds = arg_kinds(pattern, &leaves);
+
+    let mut lines = Vec::new();
+    let mut expands_li = false;
+
+    for line in &pattern.lines {
+        match line {
+            AsmLine::Rust(rust, _) => lines.push(rust.to_owned()),
+            AsmLine::Asm(asm, loc) => {
+                let tokens = lex(pattern, *loc, &asm.replace('\n', ""))?;
+                let tokens = substitute(tokens, &leaves).map_err(|(_, message)| pattern_error(pattern, *loc, message))?;
+
+                let (pushes, line_expands_li) = construct_assembly(target, paths, tokens, &kinds).map_err(|message| pattern_error(pattern, *loc, message))?;
+                expands_li |= line_expands_li;
+
+                lines.push(pushes.to_string());
+            },
+        }
+    }
+
+    let body = match TokenStream::from_str(&lines.join("\n")) {
+        Ok(body) => body,
+        Err(_) => {
+            // lexing errors carry no span, so search for the first line which cannot be lexed on its own
+            let loc = pattern.lines.iter().zip(&lines).find_map(|(line, code)| match line {
+                AsmLine::Rust(_, loc) | AsmLine::Asm(_, loc) if code.parse::<TokenStream>().is_err() => Some(*loc),
+                _ => None,
+            });
+
+            return Err(pattern_error(pattern, loc.unwrap_or(pattern.loc), "invalid token or unbalanced delimiter"));
+        },
+    };
+
+    let body = substitute(body, &leaves).map_err(|(span, message)| pattern_error(pattern, locate(pattern, span), message))?;
+
+    if let Err(err) = syn::Block::parse_within.parse2(body.clone()) {
+        return Err(pattern_error(pattern, locate(pattern, err.span()), err));
+    }
+
+    Ok((body, expands_li))
+}
+
+/// Replaces node variables (`$out`, `$1`, ...) and temporaries (`%t0`, ...) with
+/// the rust expressions they stand for
+///
+/// Works on tokens, so `$1` never matches inside of `$10` or a string literal
+fn substitute(tokens: TokenStream, leaves: &[Leaf]) -> Result<TokenStream, (Span, String)> {
+    let mut out = TokenStream::new();
+
+    let mut tokens = tokens.into_iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Punct(punct) if punct.as_char() == '$' => {
+                let mut var = tokens.next().map(|token| token.to_string()).unwrap_or_default();
+
+                // `$2.1` lexes as `$` `2.1`, deeper paths continue with `.` `1`
+                while let Some(TokenTree::Punct(dot)) = tokens.peek() {
+                    if dot.as_char() != '.' { break; }
+                    tokens.next();
+                    var.push('.');
+                    var.push_str(&tokens.next().map(|token| token.to_string()).unwrap_or_default());
+                }
+
+                let span = punct.span();
+
+                if var == "out" {
+                    out.extend(quote_spanned!(span=> node.get_out().into()));
+                } else if let Some(leaf) = leaves.iter().find(|leaf| leaf.var == var) {
+                    let name = syn::Ident::new(&leaf.name.to_string(), span);
+                    out.extend(quote!(#name));
+                } else if leaves.iter().any(|leaf| leaf.var.starts_with(&format!("{}.", var))) {
+                    return Err((span, format!("`${}` is a nested node, use its operands (`${}.1`, ...)", var, var)));
+                } else {
+                    return Err((span, format!("unknown node variable `${}`", var)));
+                }
+            },
+            TokenTree::Punct(punct) if punct.as_char() == '%' => {
+                // `%t0` is a temporary, `% t0` stays a remainder
+                let tmp = match tokens.peek() {
+                    Some(TokenTree::Ident(ident)) if punct.span().end() == ident.span().start() => {
+                        ident.to_string().strip_prefix('t').and_then(|num| num.parse::<usize>().ok())
+                    },
+                    _ => None,
+                };
+
+                match tmp {
+                    Some(num) => {
+                        tokens.next();
+                        let span = punct.span();
+                        let num = Literal::usize_unsuffixed(num);
+                        out.extend(quote_spanned!(span=> Operand::Tmp(#num)));
+                    },
+                    None => out.extend([TokenTree::Punct(punct)]),
+                }
+            },
+            TokenTree::Group(group) => {
+                let mut new = Group::new(group.delimiter(), substitute(group.stream(), leaves)?);
+                new.set_span(group.span());
+                out.extend([TokenTree::Group(new)]);
+            },
+            token => out.extend([token]),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Builds the comment header placed on top of every generated file
+///
+/// It contains the generator version and a hash of the input, so a stale
+/// generated file can be detected
+pub fn header(input: &str) -> String {
+    format!("// generated by ytbgen v{}, do not edit\n// input hash: {:016x}\n", env!("CARGO_PKG_VERSION"), hash(input))
+}
+
+/// 64-bit FNV-1a hash (stable across rust versions, unlike the std hasher)
+fn hash(input: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for byte in input.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+/// The `Mnemonic` variant of an instruction (`add` -> `Add`, `fmv.d` -> `FmvD`)
+fn variant_name(name: &str) -> String {
+    name.split('.').map(first_to_uppercase).collect()
+}
+
+fn first_to_uppercase(s: &str) -> String {
+    let mut c = s.chars();
+    match c.next() {
+        None => String::new(),
+        Some(f) => f.to_uppercase().collect::<String>() + c.as_str(),
+    }
+}
+
+/// Reports the matched pattern, only compiled in with the trace feature
+fn construct_trace(paths: &Paths, id: &Literal, pattern: &ast::Pattern) -> TokenStream {
+    let cfg = paths.trace_feature.as_ref().map(|feature| quote!(#[cfg(feature = #feature)]));
+    let loc = format!("{}:{}", pattern.file, pattern.loc.line);
+
+    match &paths.trace {
+        Some(Trace::Log) => quote! {
+            #cfg
+            log::trace!("pattern {} ({}): {}", #id, #loc, node);
+        },
+        Some(Trace::Hook(hook)) => quote! {
+            #cfg
+            #hook(#id, #loc, &node);
+        },
+        None => TokenStream::new(),
+    }
+}
+
+/// Builds the operand variables (`ls`, `rs`, `op3`) of the pattern
+fn construct_operands(paths: &Paths, pattern: &ast::Pattern) -> TokenStream {
+    let tmp_info = &paths.tmp_info;
+
+    let fail = if paths.fallible {
+        quote!(return Err(SelectError::OperandMaterialization(node)))
+    } else {
+        quote!(panic!())
+    };
+
+    leaves(pattern).into_iter()
+        .map(|leaf| {
+            let name = leaf.name;
+            let tmp = operand_tmp(leaf.tmp);
+            let op = operand_expr(&leaf.path);
+            // the temporary has the type of the node the operand belongs to
+            let owner = node_expr(&leaf.path[..leaf.path.len() - 1]);
+
+            let compile_op = if paths.fallible {
+                quote! {
+                    let Some(op) = OperationHandler::new().compile_op(&#op, consta.as_ref()) else { #fail };
+                    op
+                }
+            } else {
+                quote!(OperationHandler::new().compile_op(&#op, consta.as_ref()).unwrap())
+            };
+
+            quote! {
+                let #name = {
+                    let mut consta = None;
+                    if OperationHandler::new().requires_new_const(&#op) { consta = Some(OperationHandler::new().create_const(module)) }
+
+                    if OperationHandler::new().just_op(&#op) {
+                        #compile_op
+                    } else if OperationHandler::new().inserts_instrs(&#op) {
+                        let Some(instrs) = OperationHandler::new().compile_instrs(&#op, consta.as_ref(), #tmp_info::new(#tmp, #owner.get_ty())) else { #fail };
+                        asm.extend_from_slice(&instrs);
+                        Operand::Tmp(#tmp)
+                    } else { #fail }
+                };
+            }
+        })
+        .collect()
+}
+
+/// Copies the tied operand into `$out` if they differ
+///
+/// If `$out` is the other operand, the copy would overwrite it. Commutative
+/// patterns then swap the operands, others save it into `TIE_TMP` first
+fn construct_tie(target: ast::AstTarget, paths: &Paths, pattern: &ast::Pattern) -> Result<TokenStream, Error> {
+    let Some(tie) = pattern.tie else {
+        return Ok(TokenStream::new());
+    };
+
+    let leaves = leaves(pattern);
+
+    let Some(tied) = leaves.iter().find(|leaf| leaf.var == tie.to_string()) else {
+        return Err(pattern_error(pattern, pattern.loc, format!("`$out` is tied to `${}`, which is not an operand", tie)));
+    };
+    let other = leaves.iter().find(|leaf| leaf.var == (3 - tie).to_string());
+
+    let tied_name = &tied.name;
+
+    // `$out` gets the class of the tied operand if the pattern does not declare it
+    let class = match (pattern.variant.out, tied.kind) {
+        (Some(ast::OpVariant::Fp), _) | (_, ast::OpVariant::Fp) => ast::OpVariant::Fp,
+        _ => ast::OpVariant::Gr,
+    };
+
+    let alias = match other {
+        Some(other) if pattern.commutative => {
+            let other = &other.name;
+            quote! {
+                let (#tied_name, #other) = if tied_out == #other { (#other, #tied_name) } else { (#tied_name, #other) };
+            }
+        },
+        Some(other) => match tie_tmp(pattern) {
+            Some(other_class) => {
+                let other = &other.name;
+                let save = construct_move(target, paths, pattern, other_class, quote!(Operand::Tmp(TIE_TMP)), quote!(#other))?;
+                quote! {
+                    let #other = if tied_out == #other {
+                        #save
+                        Operand::Tmp(TIE_TMP)
+                    } else { #other };
+                }
+            },
+            None => TokenStream::new(),
+        },
+        _ => TokenStream::new(),
+    };
+
+    let copy = construct_move(target, paths, pattern, class, quote!(tied_out), quote!(#tied_name.clone()))?;
+
+    Ok(quote! {
+        let tied_out: Operand = node.get_out().into();
+        #alias
+        if tied_out != #tied_name {
+            #copy
+        }
+    })
+}
+
+/// The class of the temporary a non commutative tied pattern saves the other
+/// operand in if it aliases `$out` (None if it cannot alias)
+fn tie_tmp(pattern: &ast::Pattern) -> Option<ast::OpVariant> {
+    let tie = pattern.tie?;
+    if pattern.commutative { return None; }
+
+    let other = leaves(pattern).into_iter().find(|leaf| leaf.var == (3 - tie).to_string())?;
+
+    match other.kind {
+        ast::OpVariant::Gr | ast::OpVariant::Any => Some(ast::OpVariant::Gr),
+        ast::OpVariant::Fp => Some(ast::OpVariant::Fp),
+        ast::OpVariant::Imm | ast::OpVariant::Mem => None,
+    }
+}
+
+/// Pushes the move of the target which copies `src` into `dst` for the class
+///
+/// Floats are moved with the instruction for their type, which is looked up
+/// from the node at runtime unless the pattern has a concrete type
+fn construct_move(target: ast::AstTarget, paths: &Paths, pattern: &ast::Pattern, class: ast::OpVariant, dst: TokenStream, src: TokenStream) -> Result<TokenStream, Error> {
+    let ir = &paths.ir;
+
+    // both operands have the class of the move
+    let kinds = [(dst.to_string(), class), (src.to_string(), class)];
+
+    let build = |mnemonic: &str| {
+        // `fmv.d` is no single ident
+        let tokens = TokenStream::from_str(mnemonic).expect("valid mnemonic");
+
+        // moves are no `li`, so they never need `expand_li`
+        construct_assembly(target, paths, quote!(#tokens #dst, #src), &kinds)
+            .map(|(pushes, _)| pushes)
+            .map_err(|message| pattern_error(pattern, pattern.loc, format!("{} (`tie` copies with `{}`)", message, mnemonic)))
+    };
+
+    // the moves of f32, f64 and other types (vectors)
+    let (f32, f64, other) = match (target, class) {
+        (ast::AstTarget::X86, ast::OpVariant::Fp) => ("movss", "movsd", "movaps"),
+        (ast::AstTarget::X86, _) => return build("mov"),
+        (ast::AstTarget::AArch64, ast::OpVariant::Fp) => ("fmov", "fmov", "mov"),
+        (ast::AstTarget::AArch64, _) => return build("mov"),
+        (ast::AstTarget::RiscV, ast::OpVariant::Fp) => ("fmv.s", "fmv.d", "fmv.d"),
+        (ast::AstTarget::RiscV, _) => return build("mv"),
+    };
+
+    match pattern.variant.ty.as_deref() {
+        Some("f32") => build(f32),
+        Some("f64") => build(f64),
+        Some(ty) if !is_type_predicate(ty) => build(other),
+        ty => {
+            let (f32, f64) = (build(f32)?, build(f64)?);
+
+            // `float` only accepts f32 and f64
+            let rest = if ty == Some("float") {
+                quote!(_ => { #f64 },)
+            } else {
+                let other = build(other)?;
+                quote!(#ir::TypeMetadata::f64 => { #f64 }, _ => { #other },)
+            };
+
+            Ok(quote! {
+                match node.get_ty() {
+                    #ir::TypeMetadata::f32 => { #f32 },
+                    #rest
+                }
+            })
+        },
+    }
+}
+
+/// An operand value of a pattern which is materialized into a variable
+struct Leaf {
+    /// how the body refers to it (`1`, `2.1`)
+    var: String,
+    name: syn::Ident,
+    /// the operand indices from the root node to the operand
+    path: Vec<usize>,
+    kind: ast::OpVariant,
+    /// offset of its temporary from 0xF0
+    tmp: usize,
+}
+
+/// The materialized operands of a pattern
+///
+/// The root operands keep 0xF0..0xF2 as their temporaries, the operands of
+/// nested nodes follow from 0xF3 on. For mirrored patterns the first two root
+/// operands keep the names and temporaries they have in the pattern as written
+fn leaves(pattern: &ast::Pattern) -> Vec<Leaf> {
+    let variant = &pattern.variant;
+
+    // the operand number as written in the .td file
+    let written = |index: usize| if pattern.mirrored && index < 2 { 1 - index } else { index };
+
+    let mut leaves = Vec::new();
+
+    for (index, op) in [variant.ls, variant.rs, variant.op3].into_iter().enumerate() {
+        let Some(kind) = op else { continue };
+
+        if let Some(ast::TreeOperand::Node(..)) = variant.tree.get(index) {
+            continue;
+        }
+
+        let number = written(index);
+        let name = ["ls", "rs", "op3"][number];
+
+        leaves.push(Leaf { var: (number + 1).to_string(), name: format_ident!("{}", name), path: vec![index], kind, tmp: number });
+    }
+
+    for (index, op) in variant.tree.iter().enumerate() {
+        if let ast::TreeOperand::Node(_, operands) = op {
+            nested_leaves(&mut leaves, &[index], &[written(index) + 1], operands);
+        }
+    }
+
+    leaves
+}
+
+/// `numbers` is the path as the body refers to it (1-based, as written)
+fn nested_leaves(leaves: &mut Vec<Leaf>, path: &[usize], numbers: &[usize], operands: &[ast::TreeOperand]) {
+    for (index, op) in operands.iter().enumerate() {
+        let path = [path, &[index]].concat();
+        let numbers = [numbers, &[index + 1]].concat();
+
+        let kind = match op {
+            ast::TreeOperand::Kind(kind) => *kind,
+            ast::TreeOperand::Const(_) => ast::OpVariant::Imm,
+            ast::TreeOperand::Node(_, operands) => {
+                nested_leaves(leaves, &path, &numbers, operands);
+                continue;
+            },
+        };
+
+        let numbers = numbers.iter().map(|number| number.to_string()).collect::<Vec<_>>();
+        let tmp = 3 + leaves.iter().filter(|leaf| leaf.path.len() > 1).count();
+
+        leaves.push(Leaf {
+            var: numbers.join("."),
+            name: format_ident!("op_{}", numbers.join("_")),
+            path,
+            kind,
+            tmp,
+        });
+    }
+}
+
+/// The operand at `path` (`node.get_op_node(1).unwrap().get_op(0)` for `[1, 0]`)
+///
+/// Only used after `select` matched the nested nodes, so the unwraps cannot fail
+fn operand_expr(path: &[usize]) -> TokenStream {
+    let (last, nodes) = path.split_last().expect("operand paths are never empty");
+
+    let node = node_expr(nodes);
+    let last = Literal::usize_unsuffixed(*last);
+
+    quote!(#node.get_op(#last))
+}
+
+/// The nested node at `path` (`node.get_op_node(1).unwrap()` for `[1]`), the root node if it is empty
+fn node_expr(path: &[usize]) -> TokenStream {
+    let nodes = path.iter().map(|index| Literal::usize_unsuffixed(*index));
+    quote!(node #(.get_op_node(#nodes).unwrap())*)
+}
+
+/// The conditions a root operand of a tree pattern adds to the kind check
+fn tree_condition(pattern: &ast::Pattern, index: usize, op: &ast::TreeOperand) -> Result<Option<TokenStream>, Error> {
+    let index = Literal::usize_unsuffixed(index);
+
+    Ok(match op {
+        ast::TreeOperand::Kind(_) => None,
+        ast::TreeOperand::Const(values) => {
+            let values = values.iter().map(|value| Literal::i64_unsuffixed(*value));
+            Some(quote!(matches!(node.get_op_imm(#index), Some(#(#values)|*))))
+        },
+        ast::TreeOperand::Node(mnemonic, operands) => {
+            let opcode = lex(pattern, pattern.loc, mnemonic)?;
+
+            let mut conds = vec![quote!(matches!(node.get_opcode(), DagOpCode::#opcode))];
+
+            for (index, op) in operands.iter().enumerate() {
+                let kind = match op {
+                    ast::TreeOperand::Kind(kind) => Some(*kind),
+                    ast::TreeOperand::Const(_) => Some(ast::OpVariant::Imm),
+                    ast::TreeOperand::Node(..) => None,
+                };
+
+                if let Some(kind) = kind.filter(|kind| *kind != ast::OpVariant::Any) {
+                    let func = format_ident!("is_op_{}", kind.to_string());
+                    let index = Literal::usize_unsuffixed(index);
+                    conds.push(quote!(node.#func(#index)));
+                }
+
+                conds.extend(tree_condition(pattern, index, op)?);
+            }
+
+            // the closure shadows `node`, so nested conditions look like the root ones
+            let cond = all(&conds);
+            Some(quote!(node.get_op_node(#index).map_or(false, |node| #cond)))
+        },
+    })
+}
+
+/// Builds the statements pushing the asm of an asm line and whether they call `expand_li`
+///
+/// `kinds` are the kinds of the substituted node variables and temporaries,
+/// the arguments are checked against the operand forms of the instruction with them.
+/// Pseudo instructions of the target which are not declared with `def Inst`
+/// are expanded into the instructions they stand for
+fn construct_assembly(target: ast::AstTarget, paths: &Paths, line: TokenStream, kinds: &[(String, ast::OpVariant)]) -> Result<(TokenStream, bool), String> {
+    let mut tokens = line.into_iter().peekable();
+
+    let Some(TokenTree::Ident(mnemonic)) = tokens.next() else {
+        return Err("expected mnemonic".to_string());
+    };
+
+    let mut name = mnemonic.to_string();
+
+    // `fmv.d`
+    while let Some(TokenTree::Punct(dot)) = tokens.peek() {
+        if dot.as_char() != '.' { break; }
+        tokens.next();
+
+        match tokens.next() {
+            Some(TokenTree::Ident(part)) => name = format!("{}.{}", name, part),
+            _ => return Err(format!("expected the rest of the mnemonic after `{}.`", name)),
+        }
+    }
+
+    let args = target_specific_argument_parsing(target, split_args(tokens.collect()))?;
+
+    let instrs = match target {
+        ast::AstTarget::RiscV if !is_declared(paths, &name) => match riscv_pseudo(paths, &name, &args)? {
+            Some(Pseudo::Instrs(instrs)) => instrs,
+            Some(Pseudo::LoadImm(rd, imm)) => {
+                if !paths.insts.is_empty() && !LI_INSTRS.iter().all(|name| is_declared(paths, name)) {
+                    return Err(format!("`li` of an operand expands to {}, which are not all declared with `def Inst`", LI_INSTRS.join(", ")));
+                }
+
+                return Ok((quote!(asm.extend(expand_li(#rd, #imm));), true));
+            },
+            None => vec![(name, args)],
+        },
+        _ => vec![(name, args)],
+    };
+
+    let asm = &paths.asm;
+    let mut pushes = Vec::new();
+
+    for (name, args) in instrs {
+        let mnemonic = format_ident!("{}", variant_name(&name));
+
+        if !paths.insts.is_empty() {
+            match paths.insts.iter().find(|(variant, _)| mnemonic == variant) {
+                Some((_, forms)) => check_operands(&name, forms, &args, kinds)?,
+                None => return Err(format!("`{}` is not declared with `def Inst`", name)),
+            }
+        }
+
+        let with = format_ident!("with{}", args.len());
+
+        pushes.push(quote!(asm.push(#asm::#with(Mnemonic::#mnemonic #(, #args)*));));
+    }
+
+    Ok((quote!(#(#pushes)*), false))
+}
+
+/// Whether the instruction is declared with `def Inst`
+fn is_declared(paths: &Paths, name: &str) -> bool {
+    let variant = variant_name(name);
+    paths.insts.iter().any(|(declared, _)| *declared == variant)
+}
+
+/// Checks that some operand form of the instruction takes the arguments
+fn check_operands(name: &str, forms: &[Vec<ast::OperandSpec>], args: &[TokenStream], kinds: &[(String, ast::OpVariant)]) -> Result<(), String> {
+    let forms: Vec<_> = forms.iter().filter(|form| form.len() == args.len()).collect();
+
+    if forms.is_empty() {
+        return Err(format!("no operand form of `{}` takes {} operands", name, args.len()));
+    }
+
+    let args: Vec<_> = args.iter().map(|arg| arg_kind(arg, kinds)).collect();
+
+    let takes = |spec: &ast::OperandSpec, kind: &Option<ast::OpVariant>| match (spec, kind) {
+        (_, None | Some(ast::OpVariant::Any)) => true,
+        (ast::OperandSpec::Reg, Some(kind)) => matches!(kind, ast::OpVariant::Gr | ast::OpVariant::Fp),
+        (ast::OperandSpec::Mem, Some(kind)) => *kind == ast::OpVariant::Mem,
+        (ast::OperandSpec::RegOrMem, Some(kind)) => matches!(kind, ast::OpVariant::Gr | ast::OpVariant::Fp | ast::OpVariant::Mem),
+        (ast::OperandSpec::Imm(_) | ast::OperandSpec::UImm(_), Some(kind)) => *kind == ast::OpVariant::Imm,
+    };
+
+    if forms.iter().any(|form| form.iter().zip(&args).all(|(spec, kind)| takes(spec, kind))) {
+        return Ok(());
+    }
+
+    let args = args.iter().map(|kind| kind.unwrap_or(ast::OpVariant::Any).to_string()).collect::<Vec<_>>();
+    Err(format!("no operand form of `{}` takes ({})", name, args.join(", ")))
+}
+
+/// The kinds of the node variables and temporaries as they appear in the asm
+/// lines after they are substituted
+fn arg_kinds(pattern: &ast::Pattern, leaves: &[Leaf]) -> Vec<(String, ast::OpVariant)> {
+    let text = |code: &str| TokenStream::from_str(code).expect("valid tokens").to_string();
+
+    let mut kinds: Vec<_> = leaves.iter().map(|leaf| (leaf.name.to_string(), leaf.kind)).collect();
+    kinds.push((text("node.get_out().into()"), pattern.variant.out.unwrap_or(ast::OpVariant::Any)));
+
+    for map in &pattern.maps {
+        let Ok(num) = tmp_number(pattern, map) else { continue };
+        // temporaries pinned to a register are registers of any class
+        let kind = if map.reg.is_some() { ast::OpVariant::Gr } else { map.ty };
+
+        kinds.push((text(&format!("Operand::Tmp({})", num)), kind));
+    }
+
+    kinds
+}
+
+/// The kind of an asm line argument after the target syntax is parsed, None
+/// if it is an expression which can be anything
+fn arg_kind(arg: &TokenStream, kinds: &[(String, ast::OpVariant)]) -> Option<ast::OpVariant> {
+    let text = arg.to_string();
+
+    if let Some((_, kind)) = kinds.iter().find(|(var, _)| *var == text) {
+        return Some(*kind);
+    }
+
+    if int_literal(arg).is_some() {
+        return Some(ast::OpVariant::Imm);
+    }
+
+    let tokens: Vec<TokenTree> = arg.clone().into_iter().collect();
+
+    let path = match tokens.as_slice() {
+        [TokenTree::Ident(ty), TokenTree::Punct(first), TokenTree::Punct(second), TokenTree::Ident(name), ..]
+            if first.as_char() == ':' && second.as_char() == ':' => (ty.to_string(), name.to_string()),
+        _ => return None,
+    };
+
+    match (path.0.as_str(), path.1.as_str()) {
+        ("MemoryDispl", _) | ("Operand", "Mem") => Some(ast::OpVariant::Mem),
+        ("Operand", "Reg") => Some(ast::OpVariant::Gr),
+        ("Operand", "Imm") => Some(ast::OpVariant::Imm),
+        _ => None,
+    }
+}
+
+/// The value of an integer literal argument (`8`, `-0x10`)
+fn int_literal(arg: &TokenStream) -> Option<i64> {
+    let text = arg.to_string().replace([' ', '_'], "");
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.as_str()),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2).ok()?
+    } else {
+        digits.parse::<u64>().ok()?
+    };
+
+    // `-9223372036854775808` is no negated i64
+    if negative { 0i64.checked_sub_unsigned(value) } else { i64::try_from(value).ok() }
+}
+
+/// Splits the arguments of an asm line at the top level commas
+fn split_args(tokens: TokenStream) -> Vec<TokenStream> {
+    let mut args = Vec::new();
+    let mut arg = TokenStream::new();
+
+    for token in tokens {
+        match token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => args.push(std::mem::take(&mut arg)),
+            token => arg.extend([token]),
+        }
+    }
+
+    if !arg.is_empty() {
+        args.push(arg);
+    }
+
+    args
+}
+
+fn target_specific_argument_parsing(target: ast::AstTarget, args: Vec<TokenStream>) -> Result<Vec<TokenStream>, String> {
+    match target {
+        ast::AstTarget::X86 => x86_specifc_arg(args),
+        ast::AstTarget::AArch64 => aarch64_specific_arg(args),
+        ast::AstTarget::RiscV => riscv_specific_arg(args),
+    }
+}
+
+fn x86_specifc_arg(args: Vec<TokenStream>) -> Result<Vec<TokenStream>, String> {
+    let mut out = Vec::new();
+
+    for arg in args {
+        let mut tokens = arg.clone().into_iter();
+
+        match (tokens.next(), tokens.next()) {
+            (Some(TokenTree::Group(group)), None) if group.delimiter() == Delimiter::Bracket => { // memory displacment
+                let mut parts = Vec::new();
+                let mut part = TokenStream::new();
+
+                for token in group.stream() {
+                    match token {
+                        TokenTree::Punct(punct) if matches!(punct.as_char(), '+' | '-' | '*') => {
+                            if !part.is_empty() {
+                                parts.push(std::mem::take(&mut part));
+                            }
+
+                            parts.push(match punct.as_char() {
+                                '+' => quote!(MemoryOption::Plus),
+                                '-' => quote!(MemoryOption::Minus),
+                                _ => quote!(MemoryOption::Mul),
+                            });
+                        },
+                        token => part.extend([token]),
+                    }
+                }
+
+                if !part.is_empty() {
+                    parts.push(part);
+                }
+
+                out.push(quote!(MemoryDispl::new(#(#parts),*)));
+            },
+            _ => out.push(arg), // no memory displacment
+        }
+    }
+
+    Ok(out)
+}
+
+const AARCH64_SHIFTS: [&str; 4] = ["lsl", "lsr", "asr", "ror"];
+const AARCH64_EXTENDS: [&str; 8] = ["uxtb", "uxth", "uxtw", "uxtx", "sxtb", "sxth", "sxtw", "sxtx"];
+
+/// Parses the aarch64 operand syntax
+///
+/// - `#imm` are immediates
+/// - `[x0]`, `[x0, #8]`, `[x0, x1]` and `[x0, x1, lsl #3]` are memory operands
+/// - `[x0, #8]!` is pre-indexed and `[x0], #8` post-indexed
+/// - `x1, lsl #2` and `w1, sxtw` shift or extend the operand in front of them
+fn aarch64_specific_arg(args: Vec<TokenStream>) -> Result<Vec<TokenStream>, String> {
+    let mut out: Vec<TokenStream> = Vec::new();
+
+    // the base of the last operand if it is `[base]`, which an immediate post-indexes
+    let mut post_base = None;
+
+    for arg in args {
+        let tokens: Vec<TokenTree> = arg.clone().into_iter().collect();
+
+        let base = post_base.take();
+
+        match tokens.as_slice() {
+            [TokenTree::Group(group), rest @ ..] if group.delimiter() == Delimiter::Bracket => {
+                let pre_index = match rest {
+                    [] => false,
+                    [TokenTree::Punct(bang)] if bang.as_char() == '!' => true,
+                    _ => return Err(format!("invalid memory operand: {}", arg)),
+                };
+
+                let parts = split_args(group.stream());
+
+                let Some(base) = parts.first() else {
+                    return Err("memory operands need a base register".to_string());
+                };
+
+                if pre_index {
+                    let offset = match parts.as_slice() {
+                        [_, offset] => aarch64_imm(offset).ok_or_else(|| format!("expected an immediate offset, found: {}", offset))?,
+                        _ => return Err(format!("pre-indexed memory operands need an immediate offset: {}", arg)),
+                    };
+
+                    out.push(quote!(MemoryDispl::pre_index(#base, #offset)));
+                    continue;
+                }
+
+                out.push(match &parts[1..] {
+                    [] => {
+                        post_base = Some(base.clone());
+                        quote!(MemoryDispl::new(#base))
+                    },
+                    [offset] => match aarch64_imm(offset) {
+                        Some(offset) => match int_literal(&offset) {
+                            Some(value) if value < 0 => {
+                                let value = Literal::u64_unsuffixed(value.unsigned_abs());
+                                quote!(MemoryDispl::new(#base, MemoryOption::Minus, #value))
+                            },
+                            _ => quote!(MemoryDispl::new(#base, MemoryOption::Plus, #offset)),
+                        },
+                        None => quote!(MemoryDispl::new(#base, MemoryOption::Plus, #offset)),
+                    },
+                    [index, modifier] => {
+                        let (op, amount) = aarch64_modifier(modifier)?;
+
+                        if op == "lsl" {
+                            let amount = amount.as_ref().and_then(int_literal).filter(|amount| (0..=4).contains(amount));
+                            let Some(amount) = amount else {
+                                return Err(format!("the index needs a shift of #0 to #4: {}", modifier));
+                            };
+
+                            let scale = Literal::u64_unsuffixed(1 << amount);
+                            quote!(MemoryDispl::new(#base, MemoryOption::Plus, #index, MemoryOption::Mul, #scale))
+                        } else if AARCH64_EXTENDS.contains(&op.as_str()) {
+                            let extended = aarch64_extend(index, &op, amount);
+                            quote!(MemoryDispl::new(#base, MemoryOption::Plus, #extended))
+                        } else {
+                            return Err(format!("the index can only be shifted with lsl: {}", modifier));
+                        }
+                    },
+                    _ => return Err(format!("invalid memory operand: {}", arg)),
+                });
+            },
+            [TokenTree::Punct(hash), ..] if hash.as_char() == '#' => {
+                let imm = aarch64_imm(&arg).ok_or_else(|| format!("expected an immediate, found: {}", arg))?;
+
+                match base {
+                    Some(base) => {
+                        out.pop();
+                        out.push(quote!(MemoryDispl::post_index(#base, #imm)));
+                    },
+                    None => out.push(imm),
+                }
+            },
+            [TokenTree::Ident(ident), ..] if AARCH64_SHIFTS.contains(&ident.to_string().as_str()) || AARCH64_EXTENDS.contains(&ident.to_string().as_str()) => {
+                let (op, amount) = aarch64_modifier(&arg)?;
+
+                let Some(operand) = out.pop() else {
+                    return Err(format!("`{}` needs an operand in front of it", op));
+                };
+
+                if AARCH64_EXTENDS.contains(&op.as_str()) {
+                    out.push(aarch64_extend(&operand, &op, amount));
+                } else {
+                    let Some(amount) = amount else {
+                        return Err(format!("`{}` needs a shift amount (`{} #2`)", op, op));
+                    };
+
+                    let shift = format_ident!("{}", first_to_uppercase(&op));
+                    out.push(quote!(Operand::shifted(#operand, Shift::#shift, #amount)));
+                }
+            },
+            _ => out.push(arg),
+        }
+    }
+
+    Ok(out)
+}
+
+/// The value of an aarch64 immediate (`#8` -> `8`)
+fn aarch64_imm(arg: &TokenStream) -> Option<TokenStream> {
+    let mut tokens = arg.clone().into_iter();
+
+    match tokens.next() {
+        Some(TokenTree::Punct(hash)) if hash.as_char() == '#' => {
+            let imm: TokenStream = tokens.collect();
+            if imm.is_empty() { None } else { Some(imm) }
+        },
+        _ => None,
+    }
+}
+
+/// Splits a shift or extend into the operation and its amount (`lsl #2` -> (`lsl`, `2`))
+fn aarch64_modifier(modifier: &TokenStream) -> Result<(String, Option<TokenStream>), String> {
+    let mut tokens = modifier.clone().into_iter();
+
+    let Some(TokenTree::Ident(op)) = tokens.next() else {
+        return Err(format!("expected a shift or extend, found: {}", modifier));
+    };
+
+    let rest: TokenStream = tokens.collect();
+
+    if rest.is_empty() {
+        return Ok((op.to_string(), None));
+    }
+
+    match aarch64_imm(&rest) {
+        Some(amount) => Ok((op.to_string(), Some(amount))),
+        None => Err(format!("expected a shift amount (`#2`), found: {}", rest)),
+    }
+}
+
+fn aarch64_extend(operand: &TokenStream, op: &str, amount: Option<TokenStream>) -> TokenStream {
+    let extend = format_ident!("{}", first_to_uppercase(op));
+    let amount = amount.unwrap_or_else(|| quote!(0));
+
+    quote!(Operand::extended(#operand, Extend::#extend, #amount))
+}
+
+const RISCV_RELOCS: [&str; 4] = ["hi", "lo", "pcrel_hi", "pcrel_lo"];
+
+/// Parses the risc-v operand syntax
+///
+/// - `8($1)`, `-8($1)` and `($1)` are memory operands
+/// - `%hi(sym)`, `%lo(sym)`, `%pcrel_hi(sym)` and `%pcrel_lo(sym)` are relocations,
+///   which can also be the offset of a memory operand (`%lo(sym)($1)`)
+fn riscv_specific_arg(args: Vec<TokenStream>) -> Result<Vec<TokenStream>, String> {
+    let mut out = Vec::new();
+
+    for arg in args {
+        let tokens: Vec<TokenTree> = arg.clone().into_iter().collect();
+
+        // `offset(base)`, other calls like `Reg::zero()` are left alone
+        if let [offset @ .., TokenTree::Group(base)] = tokens.as_slice() {
+            if base.delimiter() == Delimiter::Parenthesis && !base.stream().is_empty() {
+                let offset: TokenStream = offset.iter().cloned().collect();
+                let base = base.stream();
+
+                if offset.is_empty() {
+                    out.push(quote!(MemoryDispl::new(#base)));
+                    continue;
+                }
+
+                if let Some(value) = int_literal(&offset) {
+                    out.push(if value < 0 {
+                        let value = Literal::u64_unsuffixed(value.unsigned_abs());
+                        quote!(MemoryDispl::new(#base, MemoryOption::Minus, #value))
+                    } else {
+                        quote!(MemoryDispl::new(#base, MemoryOption::Plus, #offset))
+                    });
+                    continue;
+                }
+
+                if let Some(reloc) = riscv_reloc(&offset)? {
+                    out.push(quote!(MemoryDispl::new(#base, MemoryOption::Plus, #reloc)));
+                    continue;
+                }
+            }
+        }
+
+        match riscv_reloc(&arg)? {
+            Some(reloc) => out.push(reloc),
+            None => out.push(arg),
+        }
+    }
+
+    Ok(out)
+}
+
+/// The operand of a relocation operator (`%hi(sym)` -> `Operand::hi(sym)`)
+fn riscv_reloc(arg: &TokenStream) -> Result<Option<TokenStream>, String> {
+    let tokens: Vec<TokenTree> = arg.clone().into_iter().collect();
+
+    match tokens.as_slice() {
+        [TokenTree::Punct(percent), TokenTree::Ident(op), TokenTree::Group(sym)] if percent.as_char() == '%' && sym.delimiter() == Delimiter::Parenthesis => {
+            if !RISCV_RELOCS.contains(&op.to_string().as_str()) {
+                return Err(format!("unknown relocation `%{}`, expected one of %{}", op, RISCV_RELOCS.join(", %")));
+            }
+
+            let sym = sym.stream();
+            Ok(Some(quote!(Operand::#op(#sym))))
+        },
+        _ => Ok(None),
+    }
+}
+
+/// What a risc-v pseudo instruction stands for
+enum Pseudo {
+    Instrs(Vec<(String, Vec<TokenStream>)>),
+    /// `li` of an operand which is only known at runtime, expanded by `expand_li`
+    LoadImm(TokenStream, TokenStream),
+}
+
+/// The instructions `expand_li` uses
+const LI_INSTRS: [&str; 4] = ["addi", "lui", "addiw", "slli"];
+
+/// Expands the pseudo instructions `li`, `mv`, `not`, `j`, `ret` and `nop`,
+/// None if `name` is no pseudo instruction
+fn riscv_pseudo(paths: &Paths, name: &str, args: &[TokenStream]) -> Result<Option<Pseudo>, String> {
+    let reg = &paths.reg;
+    let zero = quote!(Operand::Reg(#reg::zero()));
+
+    let count = match name {
+        "li" | "mv" | "not" => 2,
+        "j" => 1,
+        "ret" | "nop" => 0,
+        _ => return Ok(None),
+    };
+
+    if args.len() != count {
+        return Err(format!("`{}` takes {} operands", name, count));
+    }
+
+    let instr = |name: &str, args: Vec<TokenStream>| (name.to_string(), args);
+
+    let instrs = match name {
+        "li" => match int_literal(&args[1]) {
+            Some(value) => {
+                let mut steps = Vec::new();
+                li_steps(value, &mut steps);
+
+                steps.into_iter().enumerate()
+                    .map(|(index, (mnemonic, imm))| {
+                        let src = if index == 0 { zero.clone() } else { args[0].clone() };
+                        let imm = TokenStream::from_str(&imm.to_string()).expect("valid literal");
+                        let imm = quote!(Operand::Imm(#imm));
+
+                        match mnemonic {
+                            "lui" => instr(mnemonic, vec![args[0].clone(), imm]),
+                            _ => instr(mnemonic, vec![args[0].clone(), src, imm]),
+                        }
+                    })
+                    .collect()
+            },
+            None => return Ok(Some(Pseudo::LoadImm(args[0].clone(), args[1].clone()))),
+        },
+        "mv" => vec![instr("addi", vec![args[0].clone(), args[1].clone(), quote!(Operand::Imm(0))])],
+        "not" => vec![instr("xori", vec![args[0].clone(), args[1].clone(), quote!(Operand::Imm(-1))])],
+        "j" => vec![instr("jal", vec![zero, args[0].clone()])],
+        "ret" => vec![instr("jalr", vec![zero, quote!(Operand::Reg(#reg::ra())), quote!(Operand::Imm(0))])],
+        _ => vec![instr("addi", vec![zero.clone(), zero, quote!(Operand::Imm(0))])],
+    };
+
+    Ok(Some(Pseudo::Instrs(instrs)))
+}
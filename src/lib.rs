@@ -0,0 +1,12 @@
+pub mod ast;
+pub mod gen;
+
+mod builder;
+mod error;
+
+#[cfg(test)]
+mod tests;
+
+pub use ast::AstTarget;
+pub use builder::{Builder, Generated};
+pub use error::Error;
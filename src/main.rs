@@ -1,19 +1,25 @@
 
-pub mod ast;
-use std::io::Read;
+use ytbgen::{AstTarget, Builder};
 
-use crate::ast::*;
-
-pub mod gen;
-
-
-use clap::{command, Parser};
+use clap::Parser;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
     /// the input file
     input: String,
+
+    /// the target whose assembly syntax the asm lines use (x86, aarch64 or riscv64)
+    #[arg(long, default_value = "x86")]
+    target: AstTarget,
+
+    /// checks that the given generated file is up to date instead of printing the output
+    #[arg(long, value_name = "GENERATED")]
+    check: Option<String>,
+
+    /// writes a JSON source map from generated lines to the patterns into the given file
+    #[arg(long, value_name = "FILE")]
+    source_map: Option<String>,
 }
 
 fn main() {
@@ -21,32 +27,37 @@ fn main() {
 
     let infile = args.input;
 
-    let mut file = match std::fs::File::open(infile) {
-        Ok(file) => file,
+    let generated = match Builder::new().input(&infile).target(args.target).generate_code() {
+        Ok(generated) => generated,
         Err(err) => {
             eprintln!("error: {}", err);
             std::process::exit(-1);
         },
     };
 
-    let mut input = String::new();
-
-    match file.read_to_string(&mut input) {
-        Ok(_) => {},
-        Err(err) => {
-            eprintln!("error: {}", err);
+    if let Some(source_map) = args.source_map {
+        if let Err(err) = std::fs::write(&source_map, generated.source_map()) {
+            eprintln!("error: {}: {}", source_map, err);
             std::process::exit(-1);
-        },
-    };
-
-
-    input = input.replace("\r", "");
-
-    let patterns = ast::parse(&input);
-
-    let emiter = gen::CodeEmitter {
-        patterns: patterns
-    };
-
-    println!("{}", emiter.gen(AstTarget::X86));
+        }
+    }
+
+    if let Some(check) = args.check {
+        let existing = match std::fs::read_to_string(&check) {
+            Ok(existing) => existing,
+            Err(err) => {
+                eprintln!("error: {}: {}", check, err);
+                std::process::exit(-1);
+            },
+        };
+
+        if !generated.is_current(&existing) {
+            eprintln!("error: {} is out of date, regenerate it from {}", check, infile);
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    println!("{}", generated.code);
 }
@@ -0,0 +1,129 @@
+use crate::AstTarget;
+
+use super::{generate, mock::{dag::{DagNode, DagOp}, IR::{self, TypeMetadata}, *}};
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/aarch64.td", aarch64);
+
+/// The code generated for an asm line of a load, without whitespace and the
+/// trailing commas of wrapped calls
+fn line(asm: &str) -> Result<String, String> {
+    let code = generate(AstTarget::AArch64, &format!("def Pat<Load gr, gr -> gr> i64 {{ asm -> {}; }}", asm)).map_err(|err| err.message)?;
+    Ok(code.replace(char::is_whitespace, "").replace(",)", ")"))
+}
+
+fn assert_builds(asm: &str, build: &str) {
+    let code = line(asm).unwrap();
+    let build = build.replace(char::is_whitespace, "");
+    assert!(code.contains(&build), "`{}` does not build `{}`", asm, build);
+}
+
+#[test]
+fn immediates() {
+    assert_builds("add $out, $1, #8", "Asm::with3(Mnemonic::Add, node.get_out().into(), ls, 8)");
+    assert_builds("add $out, $1, #0x10", "Asm::with3(Mnemonic::Add, node.get_out().into(), ls, 0x10)");
+}
+
+#[test]
+fn memory_operands() {
+    assert_builds("ldr $out, [$1]", "MemoryDispl::new(ls)");
+    assert_builds("ldr $out, [$1, #8]", "MemoryDispl::new(ls, MemoryOption::Plus, 8)");
+    assert_builds("ldr $out, [$1, #-8]", "MemoryDispl::new(ls, MemoryOption::Minus, 8)");
+    assert_builds("ldr $out, [$1, $2]", "MemoryDispl::new(ls, MemoryOption::Plus, rs)");
+    assert_builds("ldr $out, [$1, $2, lsl #3]", "MemoryDispl::new(ls, MemoryOption::Plus, rs, MemoryOption::Mul, 8)");
+    assert_builds("ldr $out, [$1, $2, sxtw #3]", "MemoryDispl::new(ls, MemoryOption::Plus, Operand::extended(rs, Extend::Sxtw, 3))");
+}
+
+#[test]
+fn indexed_memory_operands() {
+    assert_builds("ldr $out, [$1, #8]!", "Asm::with2(Mnemonic::Ldr, node.get_out().into(), MemoryDispl::pre_index(ls, 8))");
+    assert_builds("ldr $out, [$1], #8", "Asm::with2(Mnemonic::Ldr, node.get_out().into(), MemoryDispl::post_index(ls, 8))");
+    assert_builds("ldr $out, [$1], #-16", "MemoryDispl::post_index(ls, -16)");
+
+    assert_eq!(line("ldr $out, [$1]!"), Err("pre-indexed memory operands need an immediate offset: [ls] !".to_string()));
+}
+
+#[test]
+fn shifted_and_extended_operands() {
+    assert_builds("add $out, $1, $2, lsl #2", "Asm::with3(Mnemonic::Add, node.get_out().into(), ls, Operand::shifted(rs, Shift::Lsl, 2))");
+    assert_builds("add $out, $1, $2, sxtw", "Asm::with3(Mnemonic::Add, node.get_out().into(), ls, Operand::extended(rs, Extend::Sxtw, 0))");
+    assert_builds("add $out, $1, $2, uxtw #2", "Operand::extended(rs, Extend::Uxtw, 2)");
+
+    assert_eq!(line("add $out, $1, $2, lsl"), Err("`lsl` needs a shift amount (`lsl #2`)".to_string()));
+    assert_eq!(line("ldr $out, [$1, $2, asr #3]"), Err("the index can only be shifted with lsl: asr # 3".to_string()));
+}
+
+#[test]
+fn immediates_are_checked_against_the_forms() {
+    let insts = "def Inst add : (r64, r64, r64); def Inst ldr : (r64, m64);";
+
+    let err = generate(AstTarget::AArch64, &format!("{} def Pat<Add gr, gr -> gr> i64 {{ asm -> add $out, $1, #8; }}", insts)).unwrap_err();
+    assert_eq!(err.message, "no operand form of `add` takes (gr, gr, imm)");
+
+    generate(AstTarget::AArch64, &format!("{} def Pat<Load gr -> gr> i64 {{ asm -> ldr $out, [$1], #8; }}", insts)).unwrap();
+}
+
+fn reg(num: u8) -> Operand {
+    Operand::Reg(Reg::new(num, 64))
+}
+
+fn mem(base: u8, index: Option<u8>, scale: u8, displ: i32) -> Operand {
+    Operand::Mem(MemoryDispl::from_parts(Some(Reg::new(base, 64)), index.map(|num| Reg::new(num, 64)), scale, displ))
+}
+
+#[test]
+fn parser() {
+    let asm = parse_asm("
+        add x0, x1, x2
+        add x0, x1, #4095 // the largest immediate
+        ldr x0, [x1, #-8]
+        ldr x0, [x1, x2, lsl #3]
+        ldr x0, [x1, x2, lsl #4]
+        ret
+    ");
+
+    assert_eq!(asm, Ok(vec![
+        Asm::with3(Mnemonic::Add, reg(0), reg(1), reg(2)),
+        Asm::with3(Mnemonic::Add, reg(0), reg(1), Operand::Imm(4095)),
+        Asm::with2(Mnemonic::Ldr, reg(0), mem(1, None, 1, -8)),
+        Asm::with2(Mnemonic::Ldr, reg(0), mem(1, Some(2), 8, 0)),
+        Asm::with2(Mnemonic::Ldr, reg(0), mem(1, Some(2), 16, 0)),
+        Asm::with0(Mnemonic::Ret),
+    ]));
+
+    assert_eq!(parse_asm("add x0, x1, #4096"), Err(AsmParseError::NoForm(1, Mnemonic::Add)));
+    assert_eq!(parse_asm("ldr x0, [x1, x2, lsl #5]"), Err(AsmParseError::InvalidOperand(1, "[x1, x2, lsl #5]".to_string())));
+    assert_eq!(parse_asm("ldr x0, [x1, #8]!"), Err(AsmParseError::InvalidOperand(1, "[x1, #8]!".to_string())));
+}
+
+fn compiled(node: DagNode) -> Vec<Asm> {
+    let mut asm = Vec::new();
+    compile(&mut asm, node, &mut IR::Module);
+    asm
+}
+
+fn modified(num: u8, modifier: Modifier) -> Operand {
+    Operand::Reg(Reg { modifier: Some(modifier), ..Reg::new(num, 64) })
+}
+
+#[test]
+fn shifted_and_extended_operands_build() {
+    let shl = DagNode::new(DagOpCode::Shl, TypeMetadata::i64, vec![DagOp::Gr(2), DagOp::Imm(2)], DagOp::Gr(3));
+    let add = DagNode::new(DagOpCode::Add, TypeMetadata::i64, vec![DagOp::Gr(1), DagOp::Node(Box::new(shl))], DagOp::Gr(0));
+    assert_eq!(compiled(add), [Asm::with3(Mnemonic::Add, reg(0), reg(1), modified(2, Modifier::Shift(Shift::Lsl, 2)))]);
+
+    let add = DagNode::new(DagOpCode::Add, TypeMetadata::i32, vec![DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(0));
+    assert_eq!(compiled(add), [Asm::with3(Mnemonic::Add, reg(0), reg(1), modified(2, Modifier::Extend(Extend::Uxtw, 0)))]);
+}
+
+#[test]
+fn indexed_memory_operands_build() {
+    let indexed = |indexing, displ| Operand::Mem(MemoryDispl { indexing, ..MemoryDispl::from_parts(Some(Reg::new(1, 64)), None, 1, displ) });
+
+    let load = DagNode::new(DagOpCode::Load, TypeMetadata::i64, vec![DagOp::Gr(1)], DagOp::Gr(0));
+    assert_eq!(compiled(load), [Asm::with2(Mnemonic::Ldr, reg(0), indexed(Indexing::Pre, 8))]);
+
+    let load = DagNode::new(DagOpCode::Load, TypeMetadata::i32, vec![DagOp::Gr(1)], DagOp::Gr(0));
+    assert_eq!(compiled(load), [Asm::with2(Mnemonic::Ldr, reg(0), indexed(Indexing::Post, -16))]);
+}
@@ -0,0 +1,68 @@
+use super::mock::*;
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/bits.td");
+
+fn reg(num: u8) -> Operand {
+    Operand::Reg(Reg::new(num, 0))
+}
+
+fn mem(base: u8, displ: i32) -> Operand {
+    Operand::Mem(MemoryDispl::from_parts(Some(Reg::new(base, 0)), None, 1, displ))
+}
+
+/// Encodes the asm and checks that it decodes back into it, returns the word
+fn round_trip(asm: Asm) -> u32 {
+    let mut bytes = Vec::new();
+    encode(&asm, &mut bytes).unwrap_or_else(|err| panic!("{:?}: {}", asm, err));
+
+    assert_eq!(decode(&bytes), Some((asm, 4)));
+
+    u32::from_le_bytes(bytes.try_into().expect("4 bytes"))
+}
+
+const RA: u8 = 1;
+const SP: u8 = 2;
+const A0: u8 = 10;
+const A1: u8 = 11;
+
+#[test]
+fn register_and_immediate_fields() {
+    assert_eq!(round_trip(Asm::with3(Mnemonic::Add, reg(A0), reg(A1), reg(31))), 0x01f58533);
+    assert_eq!(round_trip(Asm::with3(Mnemonic::Addi, reg(A0), reg(A1), Operand::Imm(-1))), 0xfff58513);
+    assert_eq!(round_trip(Asm::with3(Mnemonic::Addi, reg(A0), reg(A1), Operand::Imm(2047))), 0x7ff58513);
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Lui, reg(A0), Operand::Imm(-0x80000))), 0x80000537);
+    assert_eq!(round_trip(Asm::with0(Mnemonic::Ecall)), 0x00000073);
+}
+
+#[test]
+fn unsigned_fields() {
+    assert_eq!(round_trip(Asm::with3(Mnemonic::Slli, reg(A0), reg(A0), Operand::Imm(63))), 0x03f51513);
+}
+
+#[test]
+fn memory_fields() {
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Ld, reg(RA), mem(SP, 8))), 0x00813083);
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Ld, reg(RA), mem(SP, -8))), 0xff813083);
+    // the offset is split around the source register
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Sd, reg(RA), mem(SP, 8))), 0x00113423);
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Sd, reg(RA), mem(SP, -2048))), 0x80113023);
+}
+
+#[test]
+fn scrambled_fields() {
+    assert_eq!(round_trip(Asm::with3(Mnemonic::Beq, reg(A0), reg(A1), Operand::Imm(-4))), 0xfeb50ee3);
+    assert_eq!(round_trip(Asm::with3(Mnemonic::Beq, reg(A0), reg(A1), Operand::Imm(4094))), 0x7eb50fe3);
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Jal, reg(RA), Operand::Imm(-2))), 0xfffff0ef);
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Jal, reg(0), Operand::Imm(0x800))), 0x0010006f);
+}
+
+#[test]
+fn out_of_range_immediates_have_no_form() {
+    let mut bytes = Vec::new();
+
+    assert_eq!(encode(&Asm::with3(Mnemonic::Addi, reg(A0), reg(A1), Operand::Imm(2048)), &mut bytes), Err(EncodeError::NoForm(Mnemonic::Addi)));
+    assert_eq!(encode(&Asm::with3(Mnemonic::Slli, reg(A0), reg(A0), Operand::Imm(64)), &mut bytes), Err(EncodeError::NoForm(Mnemonic::Slli)));
+    assert!(bytes.is_empty());
+}
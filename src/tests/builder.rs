@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use crate::Builder;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ytbgen-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn sources_list_every_included_file() {
+    let generated = Builder::new().input("src/tests/include.td").generate_code().unwrap();
+
+    let sources = generated.sources.iter().map(|path| path.to_str().unwrap().replace('\\', "/")).collect::<Vec<_>>();
+    assert_eq!(sources, ["src/tests/include.td", "src/tests/include/insts.td", "src/tests/include/patterns.td"]);
+
+    assert!(generated.code.contains("Mnemonic::Add"));
+    assert!(generated.code.contains("DagOpCode::Sub"));
+}
+
+#[test]
+fn generate_writes_the_out_file() {
+    let dir = temp_dir("builder");
+    let out_file = dir.join("x86.rs");
+    let source_map = dir.join("x86.map.json");
+
+    let builder = Builder::new().input("src/tests/include.td").out_file(&out_file).source_map(&source_map).rerun_if_changed(false);
+    let generated = builder.generate_code().unwrap();
+    builder.generate().unwrap();
+
+    assert_eq!(std::fs::read_to_string(&out_file).unwrap(), generated.code);
+    assert_eq!(std::fs::read_to_string(&source_map).unwrap(), generated.source_map());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn generate_returns_errors() {
+    let dir = temp_dir("builder-errors");
+    let out_file = dir.join("x86.rs");
+
+    let err = Builder::new().input("src/tests/include.td").rerun_if_changed(false).generate().unwrap_err();
+    assert_eq!(err.message, "no output file given");
+
+    let err = Builder::new().out_file(&out_file).generate().unwrap_err();
+    assert_eq!(err.message, "no input file given");
+
+    let err = Builder::new().input("src/tests/bad.td").out_file(&out_file).rerun_if_changed(false).generate().unwrap_err();
+    assert_eq!(err.file.as_deref(), Some("src/tests/bad.td"));
+    assert!(!out_file.exists());
+
+    let missing = dir.join("missing").join("x86.rs");
+    let err = Builder::new().input("src/tests/include.td").out_file(&missing).rerun_if_changed(false).generate().unwrap_err();
+    assert_eq!(err.file, Some(missing.display().to_string()));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,75 @@
+use super::mock::{dag::{DagNode, DagOp}, IR::{self, TypeMetadata}, *};
+use crate::AstTarget;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Mov, Add, Sub,
+}
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/commutative.td");
+
+fn add(ops: [DagOp; 2]) -> DagNode {
+    DagNode::new(DagOpCode::Add, TypeMetadata::i64, ops.to_vec(), DagOp::Gr(0))
+}
+
+fn reg(num: u8) -> Operand {
+    Operand::Reg(Reg::new(num, 64))
+}
+
+fn compiled(node: DagNode) -> Vec<Asm> {
+    let mut asm = Vec::new();
+    compile(&mut asm, node, &mut IR::Module);
+    asm
+}
+
+#[test]
+fn swapped_operands_select_the_mirror() {
+    assert_eq!(PATTERNS.len(), 3);
+    assert_eq!(select(&add([DagOp::Gr(1), DagOp::Imm(5)])), Some(0));
+    assert_eq!(select(&add([DagOp::Imm(5), DagOp::Gr(1)])), Some(1));
+    assert_eq!(select(&add([DagOp::Imm(5), DagOp::Imm(6)])), None);
+
+    // the mirror follows the pattern it was copied from
+    assert_eq!(select(&DagNode::new(DagOpCode::Sub, TypeMetadata::i64, vec![DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(0))), Some(2));
+}
+
+#[test]
+fn mirrors_swap_the_asm_operands() {
+    let expected = [
+        Asm::with2(Mnemonic::Mov, reg(0), reg(1)),
+        Asm::with2(Mnemonic::Add, reg(0), Operand::Imm(5)),
+    ];
+
+    assert_eq!(compiled(add([DagOp::Gr(1), DagOp::Imm(5)])), expected);
+    assert_eq!(compiled(add([DagOp::Imm(5), DagOp::Gr(1)])), expected);
+}
+
+#[test]
+fn mirrors_swap_their_temporaries() {
+    let kinds = |id: PatternId| PATTERNS[id].tmps.iter().map(|tmp| (tmp.num, tmp.kind)).collect::<Vec<_>>();
+
+    assert_eq!(PATTERNS[0].operands, [OperandKind::Gr, OperandKind::Imm]);
+    assert_eq!(PATTERNS[1].operands, [OperandKind::Imm, OperandKind::Gr]);
+    assert_eq!(kinds(0), [(0xF0, OperandKind::Gr), (0xF1, OperandKind::Imm)]);
+    // `$1` keeps its temporary, it is just the second operand of the node now
+    assert_eq!(kinds(1), [(0xF1, OperandKind::Imm), (0xF0, OperandKind::Gr)]);
+
+    // `tmps()` asks for the temporaries of the swapped node operands
+    let input = std::fs::read_to_string("src/tests/commutative.td").unwrap();
+    let code = super::generate(AstTarget::X86, &input).unwrap();
+    let mirror = &code[code.find("fn tmps_with").unwrap()..];
+    let mirror = &mirror[mirror.find("Some(1) =>").unwrap()..mirror.find("Some(2) =>").unwrap()];
+
+    assert!(mirror.contains("tmp(&node.get_op(1), 0xF0)"), "{}", mirror);
+    assert!(mirror.contains("tmp(&node.get_op(0), 0xF1)"), "{}", mirror);
+}
+
+#[test]
+fn hand_written_mirrors_are_rejected() {
+    let input = "def Pat<Add gr, imm -> gr> i64 commutative; {\n    asm -> add $out, $2;\n}\n\ndef Pat<Add imm, gr -> gr> i64 {\n    asm -> add $out, $1;\n}\n";
+    let err = super::generate(AstTarget::X86, input).unwrap_err();
+
+    assert_eq!(err.to_string(), "<input>:5:1: duplicates the swapped variant of the commutative pattern at <input>:1:1 (in `def Pat<Add imm, gr -> gr> i64`)");
+}
@@ -0,0 +1,43 @@
+use crate::AstTarget;
+
+const PATTERN: &str = "
+def Pat<Add gr, gr -> gr> i64 {
+    asm -> add $out, $2;
+}
+";
+
+fn generate(config: &str) -> String {
+    super::generate(AstTarget::X86, &format!("config {{ {} }}{}", config, PATTERN)).unwrap()
+}
+
+#[test]
+fn functions_are_public_by_default() {
+    let code = generate("");
+    assert!(code.contains("pub fn compile("), "{}", code);
+}
+
+#[test]
+fn visibility_is_configurable() {
+    let code = generate("visibility = pub(crate);");
+    assert!(code.contains("pub(crate) fn compile("), "{}", code);
+
+    let code = generate("visibility = private;");
+    assert!(code.contains("\nfn compile("), "{}", code);
+    assert!(!code.contains("pub fn compile("), "{}", code);
+}
+
+#[test]
+fn paths_are_used_as_written() {
+    let code = generate("module = backend::ir::Module; node = fnord::Node; reg = regs::fnReg; use backend::fn_helpers::*;");
+
+    assert!(code.contains("module: &mut backend::ir::Module"), "{}", code);
+    assert!(code.contains("fnord::Node"), "{}", code);
+    assert!(code.contains("regs::fnReg"), "{}", code);
+    assert!(code.contains("use backend::fn_helpers::*;"), "{}", code);
+}
+
+#[test]
+fn invalid_paths_are_rejected() {
+    let err = super::generate(AstTarget::X86, &format!("config {{ module = backend::; }}{}", PATTERN)).unwrap_err();
+    assert!(err.message.starts_with("invalid config value for `module`"), "{}", err.message);
+}
@@ -0,0 +1,151 @@
+use super::mock::*;
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/encode.td");
+
+fn reg(num: u8) -> Operand {
+    Operand::Reg(Reg::new(num, 64))
+}
+
+fn mem(base: Option<u8>, index: Option<u8>, scale: u8, displ: i32) -> Operand {
+    Operand::Mem(MemoryDispl::from_parts(base.map(|num| Reg::new(num, 64)), index.map(|num| Reg::new(num, 64)), scale, displ))
+}
+
+fn bytes(asm: Asm) -> Result<Vec<u8>, EncodeError> {
+    let mut out = Vec::new();
+    encode(&asm, &mut out)?;
+    Ok(out)
+}
+
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RBX: u8 = 3;
+
+#[test]
+fn memory_operands() {
+    // add rax, [rbx + rcx*4 + 8]
+    assert_eq!(bytes(Asm::with2(Mnemonic::Add, reg(RAX), mem(Some(RBX), Some(RCX), 4, 8))), Ok(vec![0x48, 0x03, 0x44, 0x8B, 0x08]));
+    // lea rax, [rbx + rcx*4 + 0x100]
+    assert_eq!(bytes(Asm::with2(Mnemonic::Lea, reg(RAX), mem(Some(RBX), Some(RCX), 4, 0x100))), Ok(vec![0x48, 0x8D, 0x84, 0x8B, 0x00, 0x01, 0x00, 0x00]));
+    // add [rbx], rcx
+    assert_eq!(bytes(Asm::with2(Mnemonic::Add, mem(Some(RBX), None, 1, 0), reg(RCX))), Ok(vec![0x48, 0x01, 0x0B]));
+}
+
+#[test]
+fn immediates() {
+    // add rax, 0x7fffffff
+    assert_eq!(bytes(Asm::with2(Mnemonic::Add, reg(RAX), Operand::Imm(0x7fff_ffff))), Ok(vec![0x48, 0x81, 0xC0, 0xFF, 0xFF, 0xFF, 0x7F]));
+    // mov r10, 0x1122334455667788
+    assert_eq!(bytes(Asm::with2(Mnemonic::Mov, reg(10), Operand::Imm(0x1122_3344_5566_7788))), Ok(vec![0x49, 0xBA, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11]));
+    // shl rax, 63
+    assert_eq!(bytes(Asm::with2(Mnemonic::Shl, reg(RAX), Operand::Imm(63))), Ok(vec![0x48, 0xC1, 0xE0, 0x3F]));
+}
+
+#[test]
+fn sign_extended_immediates_are_signed() {
+    // the imm32 of `add r/m64, imm32` is sign extended to 64 bits
+    assert_eq!(bytes(Asm::with2(Mnemonic::Add, reg(RAX), Operand::Imm(-1))), Ok(vec![0x48, 0x81, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF]));
+    assert_eq!(bytes(Asm::with2(Mnemonic::Add, reg(RAX), Operand::Imm(0x8000_0000))), Err(EncodeError::NoForm(Mnemonic::Add)));
+}
+
+#[test]
+fn immediates_as_wide_as_the_operand_are_also_unsigned() {
+    // mov eax, 0xffffffff
+    assert_eq!(bytes(Asm::with2(Mnemonic::Mov, Operand::Reg(Reg::new(RAX, 32)), Operand::Imm(0xffff_ffff))), Ok(vec![0xC7, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF]));
+    // and al, 0xff and and al, -1
+    assert_eq!(bytes(Asm::with2(Mnemonic::And, Operand::Reg(Reg::new(RAX, 8)), Operand::Imm(0xFF))), Ok(vec![0x80, 0xE0, 0xFF]));
+    assert_eq!(bytes(Asm::with2(Mnemonic::And, Operand::Reg(Reg::new(RAX, 8)), Operand::Imm(-1))), Ok(vec![0x80, 0xE0, 0xFF]));
+    assert_eq!(bytes(Asm::with2(Mnemonic::And, Operand::Reg(Reg::new(RAX, 8)), Operand::Imm(0x100))), Err(EncodeError::NoForm(Mnemonic::And)));
+}
+
+#[test]
+fn unsigned_immediates_are_unsigned() {
+    assert_eq!(bytes(Asm::with2(Mnemonic::Shl, reg(RAX), Operand::Imm(0xFF))), Ok(vec![0x48, 0xC1, 0xE0, 0xFF]));
+    assert_eq!(bytes(Asm::with2(Mnemonic::Shl, reg(RAX), Operand::Imm(-1))), Err(EncodeError::NoForm(Mnemonic::Shl)));
+    assert_eq!(bytes(Asm::with2(Mnemonic::Shl, reg(RAX), Operand::Imm(0x100))), Err(EncodeError::NoForm(Mnemonic::Shl)));
+}
+
+/// Encodes the asm and checks that it decodes back into it
+fn round_trip(asm: Asm) -> Vec<u8> {
+    let bytes = bytes(asm.clone()).unwrap_or_else(|err| panic!("{:?}: {}", asm, err));
+    assert_eq!(decode(&bytes), Some((asm, bytes.len())));
+    bytes
+}
+
+const RSP: u8 = 4;
+const RBP: u8 = 5;
+
+#[test]
+fn rex_prefixes() {
+    // add r8, r9: REX.R and REX.B
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Add, reg(8), reg(9))), [0x4D, 0x03, 0xC1]);
+    // add rax, [r13 + r14*8 + 0x1000]: REX.X and REX.B
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Add, reg(RAX), mem(Some(13), Some(14), 8, 0x1000))), [0x4B, 0x03, 0x84, 0xF5, 0x00, 0x10, 0x00, 0x00]);
+    // add [rbx - 8], r15
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Add, mem(Some(RBX), None, 1, -8), reg(15))), [0x4C, 0x01, 0x7B, 0xF8]);
+    // mov r10, 0x1122334455667788: the register in the opcode
+    round_trip(Asm::with2(Mnemonic::Mov, reg(10), Operand::Imm(0x1122_3344_5566_7788)));
+}
+
+#[test]
+fn sib_bytes() {
+    round_trip(Asm::with2(Mnemonic::Add, reg(RAX), mem(Some(RBX), Some(RCX), 4, 8)));
+    round_trip(Asm::with2(Mnemonic::Lea, reg(RAX), mem(Some(RBX), Some(RCX), 2, -0x100)));
+    // lea rax, [rcx*8 + 16]: no base, so there always is a 32 bit displacement
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Lea, reg(RAX), mem(None, Some(RCX), 8, 16))), [0x48, 0x8D, 0x04, 0xCD, 0x10, 0x00, 0x00, 0x00]);
+    // r12 is an index, rsp is none
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Lea, reg(RAX), mem(Some(RAX), Some(12), 2, 0))), [0x4A, 0x8D, 0x04, 0x60]);
+    assert_eq!(bytes(Asm::with2(Mnemonic::Lea, reg(RAX), mem(Some(RAX), Some(RSP), 2, 0))), Err(EncodeError::UnencodableOperand(Mnemonic::Lea)));
+}
+
+#[test]
+fn rbp_and_r13_need_a_displacement() {
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Add, reg(RAX), mem(Some(RBP), None, 1, 0))), [0x48, 0x03, 0x45, 0x00]);
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Add, reg(RAX), mem(Some(13), None, 1, 0))), [0x49, 0x03, 0x45, 0x00]);
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Add, reg(RAX), mem(Some(RBP), Some(RCX), 1, 0))), [0x48, 0x03, 0x44, 0x0D, 0x00]);
+}
+
+#[test]
+fn rsp_and_r12_need_a_sib_byte() {
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Add, reg(RAX), mem(Some(RSP), None, 1, 0))), [0x48, 0x03, 0x04, 0x24]);
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Add, reg(RAX), mem(Some(12), None, 1, 0))), [0x49, 0x03, 0x04, 0x24]);
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Lea, reg(RAX), mem(Some(RSP), None, 1, 8))), [0x48, 0x8D, 0x44, 0x24, 0x08]);
+}
+
+#[test]
+fn immediates_round_trip() {
+    round_trip(Asm::with2(Mnemonic::Add, reg(RAX), Operand::Imm(-5)));
+    round_trip(Asm::with2(Mnemonic::Add, mem(Some(RBX), None, 1, 0), Operand::Imm(i32::MIN as i64)));
+    // unsigned immediates are not sign extended
+    assert_eq!(round_trip(Asm::with2(Mnemonic::Shl, reg(RAX), Operand::Imm(0xFF))), [0x48, 0xC1, 0xE0, 0xFF]);
+}
+
+const SIL: u8 = 6;
+
+#[test]
+fn byte_registers_4_to_7_need_a_rex_prefix() {
+    // and sil, 1
+    let sil = Asm::with2(Mnemonic::And, Operand::Reg(Reg::new(SIL, 8)), Operand::Imm(1));
+    assert_eq!(bytes(sil.clone()), Ok(vec![0x40, 0x80, 0xE6, 0x01]));
+    assert_eq!(decode(&[0x40, 0x80, 0xE6, 0x01]), Some((sil, 4)));
+
+    // and dh, 1
+    let dh = Asm::with2(Mnemonic::And, Operand::Reg(Reg::high_byte(SIL)), Operand::Imm(1));
+    assert_eq!(bytes(dh.clone()), Ok(vec![0x80, 0xE6, 0x01]));
+    assert_eq!(decode(&[0x80, 0xE6, 0x01]), Some((dh, 3)));
+
+    // al does not need one
+    assert_eq!(bytes(Asm::with2(Mnemonic::And, Operand::Reg(Reg::new(RAX, 8)), Operand::Imm(1))), Ok(vec![0x80, 0xE0, 0x01]));
+}
+
+#[test]
+fn high_byte_registers_cannot_have_a_rex_prefix() {
+    // mov byte [r8], dh
+    let asm = Asm::with2(Mnemonic::Mov, mem(Some(8), None, 1, 0), Operand::Reg(Reg::high_byte(SIL)));
+    assert_eq!(bytes(asm), Err(EncodeError::UnencodableOperand(Mnemonic::Mov)));
+
+    // mov byte [rbx], dh is fine
+    let asm = Asm::with2(Mnemonic::Mov, mem(Some(RBX), None, 1, 0), Operand::Reg(Reg::high_byte(SIL)));
+    assert_eq!(bytes(asm), Ok(vec![0x88, 0x33]));
+}
@@ -0,0 +1,60 @@
+use super::mock::{dag::{DagNode, DagOp}, IR::{self, TypeMetadata}, *};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Add, Mov,
+}
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/fallible.td");
+
+fn node(opcode: DagOpCode, ops: Vec<DagOp>) -> DagNode {
+    DagNode::new(opcode, TypeMetadata::i64, ops, DagOp::Gr(0))
+}
+
+fn compile_node(node: DagNode) -> Result<Vec<Asm>, SelectError> {
+    let mut asm = Vec::new();
+    compile(&mut asm, node, &mut IR::Module)?;
+    Ok(asm)
+}
+
+#[test]
+fn supported_nodes_compile() {
+    let asm = compile_node(node(DagOpCode::Add, vec![DagOp::Gr(0), DagOp::Gr(1)])).unwrap();
+    assert_eq!(asm.iter().map(Asm::mnemonic).collect::<Vec<_>>(), [Mnemonic::Add]);
+}
+
+#[test]
+fn unsupported_opcodes_are_errors() {
+    let mul = node(DagOpCode::Mul, vec![DagOp::Gr(1), DagOp::Gr(2)]);
+
+    match compile_node(mul.clone()) {
+        Err(SelectError::UnsupportedOpcode(node)) => assert_eq!(node, mul),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn unsupported_operands_are_errors() {
+    let add = node(DagOpCode::Add, vec![DagOp::Gr(1), DagOp::Imm(2)]);
+
+    match compile_node(add.clone()) {
+        Err(SelectError::UnsupportedOperands(node)) => assert_eq!(node, add),
+        other => panic!("{:?}", other),
+    }
+
+    let err = compile_node(add).unwrap_err();
+    assert!(err.to_string().starts_with("not yet compilable variant: "), "{}", err);
+}
+
+#[test]
+fn operands_which_cannot_be_materialized_are_errors() {
+    let inner = node(DagOpCode::Add, vec![DagOp::Gr(1), DagOp::Gr(2)]);
+    let copy = node(DagOpCode::Copy, vec![DagOp::Node(Box::new(inner))]);
+
+    match compile_node(copy.clone()) {
+        Err(SelectError::OperandMaterialization(node)) => assert_eq!(node, copy),
+        other => panic!("{:?}", other),
+    }
+}
@@ -0,0 +1,90 @@
+use crate::AstTarget;
+
+use super::generate;
+
+const INSTS: &str = "
+    def Inst mov : (r64, r/m64), (r/m64, r64), (r/m64, imm32);
+    def Inst add : (r64, r/m64), (r/m64, imm32);
+    def Inst lea : (r64, m64);
+";
+
+fn check(pattern: &str) -> Result<(), String> {
+    generate(AstTarget::X86, &format!("{}{}", INSTS, pattern)).map(|_| ()).map_err(|err| err.message)
+}
+
+#[test]
+fn operands_of_the_forms_are_accepted() {
+    check("def Pat<Add gr, imm -> gr> i64 { asm -> mov $out, $1; asm -> add $out, $2; }").unwrap();
+    check("def Pat<Add gr, gr -> mem> i64 map %t0, gr; { asm -> mov %t0, $1; asm -> add %t0, $2; asm -> mov $out, %t0; }").unwrap();
+    check("def Pat<Copy mem -> gr> i64 { asm -> lea $out, $1; }").unwrap();
+    check("def Pat<Copy gr -> gr> i64 { asm -> lea $out, [$1 + 8]; }").unwrap();
+}
+
+#[test]
+fn immediates_need_an_immediate_form() {
+    let err = check("def Pat<Add imm, gr -> gr> i64 { asm -> mov $out, $2; asm -> add $1, $out; }").unwrap_err();
+    assert_eq!(err, "no operand form of `add` takes (imm, gr)");
+
+    let err = check("def Pat<Copy gr -> gr> i64 { asm -> add 1, $1; }").unwrap_err();
+    assert_eq!(err, "no operand form of `add` takes (imm, gr)");
+}
+
+#[test]
+fn registers_are_no_memory() {
+    let err = check("def Pat<Copy gr -> gr> i64 { asm -> lea $out, $1; }").unwrap_err();
+    assert_eq!(err, "no operand form of `lea` takes (gr, gr)");
+
+    let err = check("def Pat<Copy mem -> mem> i64 { asm -> mov $out, $1; }").unwrap_err();
+    assert_eq!(err, "no operand form of `mov` takes (mem, mem)");
+}
+
+#[test]
+fn expressions_are_not_checked() {
+    check("def Pat<Copy gr -> gr> i64 { asm -> lea $out, Operand::Tmp(3); }").unwrap();
+}
+
+#[test]
+fn operand_counts_are_checked() {
+    let err = check("def Pat<Copy gr -> gr> i64 { asm -> mov $out; }").unwrap_err();
+    assert_eq!(err, "no operand form of `mov` takes 1 operands");
+}
+
+#[test]
+fn undeclared_instructions_are_rejected() {
+    let err = check("def Pat<Copy gr -> gr> i64 { asm -> movq $out, $1; }").unwrap_err();
+    assert_eq!(err, "`movq` is not declared with `def Inst`");
+}
+
+#[test]
+fn tie_moves_are_checked() {
+    let tie = "def Inst sub : (r64, r64); def Pat<Sub gr, gr -> gr> i64 tie $out = $1; { asm -> sub $out, $2; }";
+
+    generate(AstTarget::X86, &format!("def Inst mov : (r64, r64); {}", tie)).unwrap();
+
+    let err = generate(AstTarget::X86, &format!("def Inst mov : (r64, imm32); {}", tie)).unwrap_err();
+    assert_eq!(err.message, "no operand form of `mov` takes (gr, gr) (`tie` copies with `mov`)");
+}
+
+#[test]
+fn fp_ties_need_the_fp_moves() {
+    let tie = "def Inst subss : (xmm, xmm); def Pat<Sub fp, fp -> fp> float tie $out = $1; { asm -> subss $out, $2; }";
+
+    generate(AstTarget::X86, &format!("def Inst movss : (xmm, xmm); def Inst movsd : (xmm, xmm); {}", tie)).unwrap();
+
+    let err = generate(AstTarget::X86, &format!("def Inst movss : (xmm, xmm); {}", tie)).unwrap_err();
+    assert_eq!(err.message, "`movsd` is not declared with `def Inst` (`tie` copies with `movsd`)");
+}
+
+#[test]
+fn immediates_need_a_width() {
+    generate(AstTarget::X86, "def Inst add : (r64, imm); def Pat<Add gr, imm -> gr> i64 { asm -> add $out, $2; }").unwrap();
+
+    let err = generate(AstTarget::X86, "def Inst add : (r64, imm) = { REX.W 81 /0 id };").unwrap_err();
+    assert_eq!(err.message, "`imm` takes any value, which encoded forms can't, give it a width (`imm32`, ...)");
+
+    let err = generate(AstTarget::X86, "def Inst add : (r64, imm65);").unwrap_err();
+    assert_eq!(err.message, "`imm65` needs a width of 1 to 64 bits (`imm32`, `uimm6`, ...)");
+
+    let err = generate(AstTarget::X86, "def Inst add : (r/m64, imm8) = { REX.W 81 /0 id };").unwrap_err();
+    assert_eq!(err.message, "the encoding has a 32 bit immediate, but the operand is 8 bits wide");
+}
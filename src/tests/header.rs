@@ -0,0 +1,36 @@
+use crate::{gen, Builder, Generated};
+
+fn generated(input: &str) -> Generated {
+    Builder::new().input(input).generate_code().unwrap()
+}
+
+#[test]
+fn output_is_reproducible() {
+    for input in ["src/tests/select.td", "src/tests/encode.td", "src/tests/parse.td"] {
+        assert_eq!(generated(input), generated(input), "{}", input);
+    }
+}
+
+#[test]
+fn header_names_the_version_and_input_hash() {
+    let code = generated("src/tests/select.td").code;
+    let input = std::fs::read_to_string("src/tests/select.td").unwrap();
+
+    assert!(code.starts_with(&gen::header(&input)));
+    assert!(code.starts_with(&format!("// generated by ytbgen v{}, do not edit\n// input hash: ", env!("CARGO_PKG_VERSION"))));
+
+    // the hash changes with every byte of the input
+    assert_ne!(gen::header(&input), gen::header(&format!("{} ", input)));
+    assert_eq!(gen::header("a"), format!("// generated by ytbgen v{}, do not edit\n// input hash: af63dc4c8601ec8c\n", env!("CARGO_PKG_VERSION")));
+}
+
+#[test]
+fn stale_files_are_not_current() {
+    let generated = generated("src/tests/select.td");
+
+    assert!(generated.is_current(&generated.code));
+    assert!(generated.is_current(&format!("{}\n", generated.code.replace('\n', "\r\n"))));
+
+    let stale = generated.code.replacen("Mnemonic::Add3", "Mnemonic::Add", 1);
+    assert!(!generated.is_current(&stale));
+}
@@ -0,0 +1,48 @@
+use super::mock::{dag::{DagNode, DagOp}, IR::{self, TypeMetadata}, *};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Imul, Mul,
+}
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/implicit.td");
+
+fn mul() -> DagNode {
+    DagNode::new(DagOpCode::Mul, TypeMetadata::i64, vec![DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(0))
+}
+
+fn names(implicit: Vec<Implicit>) -> Vec<String> {
+    implicit.into_iter().map(|implicit| match implicit {
+        Implicit::Reg(reg) => reg.to_string(),
+        Implicit::Flags => "flags".to_string(),
+    }).collect()
+}
+
+fn mnemonics(goal: CostGoal) -> Vec<Mnemonic> {
+    let mut asm: Vec<Asm> = Vec::new();
+    compile_with(&mut asm, mul(), &mut IR::Module, goal);
+    asm.iter().map(Asm::mnemonic).collect()
+}
+
+#[test]
+fn implicit_accesses_follow_the_goal() {
+    assert_eq!(mnemonics(CostGoal::Instrs), [Mnemonic::Imul]);
+    assert_eq!(names(uses_with(&mul(), CostGoal::Instrs)), Vec::<String>::new());
+    assert_eq!(names(defs_with(&mul(), CostGoal::Instrs)), ["flags"]);
+
+    assert_eq!(mnemonics(CostGoal::Size), [Mnemonic::Mul]);
+    assert_eq!(names(uses_with(&mul(), CostGoal::Size)), ["rax"]);
+    assert_eq!(names(defs_with(&mul(), CostGoal::Size)), ["rax", "rdx", "flags"]);
+}
+
+#[test]
+fn default_goal_matches_compile() {
+    let mut asm = Vec::new();
+    compile(&mut asm, mul(), &mut IR::Module);
+    assert_eq!(asm.iter().map(Asm::mnemonic).collect::<Vec<_>>(), [Mnemonic::Imul]);
+
+    assert_eq!(names(uses(&mul())), Vec::<String>::new());
+    assert_eq!(names(defs(&mul())), ["flags"]);
+}
@@ -0,0 +1,52 @@
+use super::mock::{dag::DagNode, IR, *};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Mov, Add, Div, Movsd,
+}
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/info.td");
+
+fn after_div(_: &mut Vec<Asm>, _: DagNode, _: &mut IR::Module) {}
+
+fn temporaries(id: PatternId) -> Vec<(usize, OperandKind)> {
+    PATTERNS[id].tmps.iter().map(|tmp| (tmp.num, tmp.kind)).collect()
+}
+
+#[test]
+fn patterns_describe_their_operands() {
+    assert_eq!(PATTERNS.len(), 3);
+
+    assert_eq!(PATTERNS[0].mnemonic, "Add");
+    assert_eq!(PATTERNS[0].operands, [OperandKind::Gr, OperandKind::Imm]);
+    assert_eq!(PATTERNS[0].out, Some(OperandKind::Gr));
+    assert_eq!(PATTERNS[0].ty, Some("i64"));
+
+    assert_eq!(PATTERNS[2].mnemonic, "Load");
+    assert_eq!(PATTERNS[2].operands, [OperandKind::Mem]);
+    assert_eq!(PATTERNS[2].out, Some(OperandKind::Fp));
+    assert_eq!(PATTERNS[2].ty, None);
+}
+
+#[test]
+fn patterns_describe_their_side_effects() {
+    assert_eq!(PATTERNS[1].ty, Some("int"));
+    // the operands are materialized into 0xF0 and 0xF1, `%t0` follows them
+    assert_eq!(temporaries(1), [(0xF0, OperandKind::Gr), (0xF1, OperandKind::Gr), (0, OperandKind::Gr)]);
+    assert_eq!(PATTERNS[1].overwrittes, ["rax", "rdx"]);
+    assert_eq!(PATTERNS[1].hook, Some("after_div"));
+
+    assert_eq!(temporaries(0), [(0xF0, OperandKind::Gr), (0xF1, OperandKind::Imm)]);
+    assert!(PATTERNS[0].overwrittes.is_empty());
+    assert_eq!(PATTERNS[0].hook, None);
+}
+
+#[test]
+fn patterns_know_their_location() {
+    assert!(PATTERNS.iter().all(|pattern| pattern.file.replace('\\', "/").ends_with("src/tests/info.td")));
+
+    let locations = PATTERNS.iter().map(|pattern| (pattern.line, pattern.col)).collect::<Vec<_>>();
+    assert_eq!(locations, [(6, 1), (11, 1), (16, 1)]);
+}
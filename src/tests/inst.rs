@@ -0,0 +1,21 @@
+use super::mock::{dag::{DagNode, DagOp}, IR::{self, TypeMetadata}, *};
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/inst.td");
+
+#[test]
+fn patterns_use_the_declared_mnemonics() {
+    let mut asm = Vec::new();
+    compile(&mut asm, DagNode::new(DagOpCode::Add, TypeMetadata::i64, vec![DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(0)), &mut IR::Module);
+
+    assert_eq!(asm.iter().map(Asm::mnemonic).collect::<Vec<_>>(), [Mnemonic::Mov, Mnemonic::Add]);
+}
+
+#[test]
+fn mnemonics_know_their_names_and_forms() {
+    assert_eq!(Mnemonic::Mov.to_string(), "mov");
+    assert_eq!(Mnemonic::Add.forms(), [&["r64", "r/m64"][..], &["r/m64", "imm32"][..]]);
+    // without forms it takes no operands
+    assert_eq!(Mnemonic::Ret.forms(), [&[] as &[&str]]);
+}
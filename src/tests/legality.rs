@@ -0,0 +1,65 @@
+use super::mock::{dag::{DagNode, DagOp}, IR::{StdTypeMetadata, TypeMetadata, VecTy}, *};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Add, Sub, Mul, Mulps, Or,
+}
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/legality.td");
+
+const TYPES: [TypeMetadata; 11] = [
+    TypeMetadata::u8, TypeMetadata::u16, TypeMetadata::u32, TypeMetadata::u64,
+    TypeMetadata::i8, TypeMetadata::i16, TypeMetadata::i32, TypeMetadata::i64,
+    TypeMetadata::ptr, TypeMetadata::f32, TypeMetadata::f64,
+];
+
+fn node(opcode: DagOpCode, ty: TypeMetadata) -> DagNode {
+    DagNode::new(opcode, ty, vec![DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(0))
+}
+
+/// `legal_types` lists exactly the types `select` finds a pattern for
+fn assert_agrees(opcode: DagOpCode) {
+    for ty in TYPES {
+        let node = node(opcode, ty);
+        assert_eq!(can_select(&node), select(&node).is_some());
+        assert_eq!(legal_types(opcode).contains(&ty), can_select(&node), "{:?} {}", opcode, ty);
+    }
+}
+
+#[test]
+fn unsigned_excludes_floats() {
+    assert_agrees(DagOpCode::Add);
+
+    assert!(!can_select(&node(DagOpCode::Add, TypeMetadata::f32)));
+    assert!(!can_select(&node(DagOpCode::Add, TypeMetadata::i32)));
+    assert!(can_select(&node(DagOpCode::Add, TypeMetadata::u32)));
+    assert_eq!(legal_types(DagOpCode::Add), [TypeMetadata::u8, TypeMetadata::u16, TypeMetadata::u32, TypeMetadata::u64, TypeMetadata::ptr]);
+}
+
+#[test]
+fn no_float_accepts_integers() {
+    assert_agrees(DagOpCode::Sub);
+    assert!(!can_select(&node(DagOpCode::Sub, TypeMetadata::f64)));
+}
+
+#[test]
+fn ptr_is_an_unsigned_integer() {
+    assert_agrees(DagOpCode::Or);
+
+    assert!(!can_select(&node(DagOpCode::Or, TypeMetadata::ptr)));
+    assert_eq!(legal_types(DagOpCode::Or), [TypeMetadata::i8, TypeMetadata::i16, TypeMetadata::i32, TypeMetadata::i64]);
+}
+
+#[test]
+fn concrete_types_are_listed_as_written() {
+    assert_agrees(DagOpCode::Mul);
+
+    let vector = TypeMetadata::Vector(VecTy { size: 4, ty: StdTypeMetadata::f32 });
+    let node = DagNode::new(DagOpCode::Mul, vector, vec![DagOp::Fp(1), DagOp::Fp(2)], DagOp::Fp(0));
+
+    assert!(can_select(&node));
+    assert!(can_select(&self::node(DagOpCode::Mul, TypeMetadata::ptr)));
+    assert_eq!(legal_types(DagOpCode::Mul), [TypeMetadata::ptr, vector]);
+}
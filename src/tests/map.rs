@@ -0,0 +1,52 @@
+use super::mock::{dag::{DagNode, DagOp, DagTmpInfo}, IR::TypeMetadata, *};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Mov, Movsx, Add, Cvtss2sd, Subsd, Cvtsd2ss, Div,
+}
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/map.td");
+
+fn node(opcode: DagOpCode, ty: TypeMetadata, ops: [DagOp; 2], out: DagOp) -> DagNode {
+    DagNode::new(opcode, ty, ops.to_vec(), out)
+}
+
+fn tmp(num: usize, size: TypeMetadata, class: &'static str, reg: Option<Reg>) -> DagTmpInfo {
+    DagTmpInfo { num, size, class, reg }
+}
+
+#[test]
+fn typed_temporaries_keep_their_type() {
+    let add = node(DagOpCode::Add, TypeMetadata::i32, [DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(0));
+    assert_eq!(tmps(&add), [tmp(0, TypeMetadata::i64, "gr", None)]);
+
+    let sub = node(DagOpCode::Sub, TypeMetadata::f32, [DagOp::Fp(1), DagOp::Fp(2)], DagOp::Fp(0));
+    assert_eq!(tmps(&sub), [tmp(1, TypeMetadata::f64, "fp", None)]);
+}
+
+#[test]
+fn pinned_temporaries_require_their_register() {
+    let div = node(DagOpCode::Div, TypeMetadata::i64, [DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(0));
+
+    assert_eq!(tmps(&div), [
+        tmp(0, TypeMetadata::i64, "", Some(Reg::rcx())),
+        // untyped temporaries have the type of the node
+        tmp(1, TypeMetadata::i64, "gr", None),
+    ]);
+}
+
+/// The mapped temporary `num` of a pattern in `PATTERNS`
+fn described(id: PatternId, num: usize) -> Temporary {
+    *PATTERNS[id].tmps.iter().find(|tmp| tmp.num == num).expect("mapped temporary")
+}
+
+#[test]
+fn temporaries_are_described_in_patterns() {
+    let temporary = described(0, 0);
+    assert_eq!((temporary.kind, temporary.ty, temporary.reg), (OperandKind::Gr, Some("i64"), None));
+
+    let temporary = described(2, 0);
+    assert_eq!((temporary.kind, temporary.ty, temporary.reg), (OperandKind::Any, None, Some("rcx")));
+}
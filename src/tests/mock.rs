@@ -0,0 +1,483 @@
+//! A mock of the runtime the generated code is written against
+//!
+//! Register names are the union of the x86, risc-v and aarch64 names, `sp`
+//! and `fp` are the risc-v ones. `Asm` takes `Operand`s only, so asm lines
+//! of the tests can't use immediates or the x86 memory syntax. The aarch64
+//! constructors (`MemoryDispl::pre_index`, `Operand::shifted`, ...) return
+//! `Operand`s, shifts and extends are kept in the register
+
+#![allow(non_camel_case_types, non_snake_case, dead_code)]
+
+use std::{fmt::Display, str::FromStr};
+
+pub mod IR {
+    use std::fmt::Display;
+
+    pub struct Module;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum TypeMetadata {
+        u8, u16, u32, u64,
+        i8, i16, i32, i64,
+        ptr,
+        f32, f64,
+        Vector(VecTy),
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct VecTy {
+        pub size: usize,
+        pub ty: StdTypeMetadata,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum StdTypeMetadata {
+        i32,
+        f32,
+    }
+
+    impl TypeMetadata {
+        pub fn intenger(&self) -> bool {
+            !self.float() && !matches!(self, TypeMetadata::Vector(_))
+        }
+
+        pub fn signed(&self) -> bool {
+            matches!(self, TypeMetadata::i8 | TypeMetadata::i16 | TypeMetadata::i32 | TypeMetadata::i64)
+        }
+
+        pub fn float(&self) -> bool {
+            matches!(self, TypeMetadata::f32 | TypeMetadata::f64)
+        }
+    }
+
+    impl Display for TypeMetadata {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+}
+
+pub mod dag {
+    use std::fmt::Display;
+
+    use super::{IR::TypeMetadata, DagOpCode, Reg};
+
+    /// An operand or the output of a node
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum DagOp {
+        Gr(u8),
+        Fp(u8),
+        Imm(i64),
+        Mem(u8),
+        /// defined by a node which can be folded into its user
+        Node(Box<DagNode>),
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct DagNode {
+        pub opcode: DagOpCode,
+        pub ty: TypeMetadata,
+        pub ops: Vec<DagOp>,
+        pub out: DagOp,
+    }
+
+    impl DagNode {
+        pub fn new(opcode: DagOpCode, ty: TypeMetadata, ops: Vec<DagOp>, out: DagOp) -> Self {
+            Self { opcode, ty, ops, out }
+        }
+
+        pub fn get_opcode(&self) -> DagOpCode {
+            self.opcode
+        }
+
+        pub fn get_ty(&self) -> TypeMetadata {
+            self.ty
+        }
+
+        pub fn is_ty(&self, ty: TypeMetadata) -> bool {
+            self.ty == ty
+        }
+
+        pub fn get_op(&self, index: usize) -> DagOp {
+            self.ops[index].clone()
+        }
+
+        pub fn get_out(&self) -> DagOp {
+            self.out.clone()
+        }
+
+        pub fn get_op_node(&self, index: usize) -> Option<DagNode> {
+            match self.ops.get(index) {
+                Some(DagOp::Node(node)) => Some(*node.clone()),
+                _ => None,
+            }
+        }
+
+        pub fn get_op_imm(&self, index: usize) -> Option<i64> {
+            match self.ops.get(index) {
+                Some(DagOp::Imm(imm)) => Some(*imm),
+                _ => None,
+            }
+        }
+
+        pub fn is_op_gr(&self, index: usize) -> bool {
+            matches!(self.ops.get(index), Some(DagOp::Gr(_)))
+        }
+
+        pub fn is_op_fp(&self, index: usize) -> bool {
+            matches!(self.ops.get(index), Some(DagOp::Fp(_)))
+        }
+
+        pub fn is_op_imm(&self, index: usize) -> bool {
+            matches!(self.ops.get(index), Some(DagOp::Imm(_)))
+        }
+
+        pub fn is_op_mem(&self, index: usize) -> bool {
+            matches!(self.ops.get(index), Some(DagOp::Mem(_)))
+        }
+
+        pub fn is_out_gr(&self) -> bool {
+            matches!(self.out, DagOp::Gr(_))
+        }
+
+        pub fn is_out_fp(&self) -> bool {
+            matches!(self.out, DagOp::Fp(_))
+        }
+
+        pub fn is_out_mem(&self) -> bool {
+            matches!(self.out, DagOp::Mem(_))
+        }
+    }
+
+    impl Display for DagNode {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct DagTmpInfo {
+        pub num: usize,
+        pub size: TypeMetadata,
+        /// `gr`, `fp` or `mem`, empty for temporaries pinned to a register
+        pub class: &'static str,
+        pub reg: Option<Reg>,
+    }
+
+    impl DagTmpInfo {
+        pub fn new(num: usize, size: TypeMetadata) -> Self {
+            Self { num, size, class: "", reg: None }
+        }
+
+        pub fn require_gr(&mut self) {
+            self.class = "gr";
+        }
+
+        pub fn require_fp(&mut self) {
+            self.class = "fp";
+        }
+
+        pub fn require_mem(&mut self) {
+            self.class = "mem";
+        }
+
+        pub fn require_reg(&mut self, reg: Reg) {
+            self.reg = Some(reg);
+        }
+    }
+}
+
+use dag::{DagOp, DagTmpInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DagOpCode {
+    Add, Sub, Mul, Div, And, Or, Xor, Shl,
+    /// shifts in the sign bit for signed types
+    Shr,
+    /// the compares have the type of their operands and output 0 or 1
+    CmpEq, CmpNe, CmpLt,
+    /// branches to the offset of their last operand
+    Br, BrEq, BrNe, BrLt, BrGe,
+    Copy, Load, Store, Ret,
+}
+
+const X86_64: [&str; 16] = ["rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15"];
+const X86_32: [&str; 16] = ["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d", "r13d", "r14d", "r15d"];
+const RISCV: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "fp", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+/// A register number with the size in bits of the form it was decoded from
+/// (0 if the form has none), registers are compared by number only (and
+/// whether they are one of ah, ch, dh and bh or shifted)
+#[derive(Debug, Clone, Copy)]
+pub struct Reg {
+    pub num: u8,
+    pub size: usize,
+    pub high: bool,
+    /// the aarch64 shift or extend of the register operand
+    pub modifier: Option<Modifier>,
+}
+
+impl PartialEq for Reg {
+    fn eq(&self, other: &Self) -> bool {
+        self.num == other.num && self.high == other.high && self.modifier == other.modifier
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shift {
+    Lsl, Lsr, Asr, Ror,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extend {
+    Uxtb, Uxth, Uxtw, Uxtx, Sxtb, Sxth, Sxtw, Sxtx,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    Shift(Shift, u8),
+    Extend(Extend, u8),
+}
+
+impl Reg {
+    pub fn new(num: u8, size: usize) -> Self {
+        Self { num, size, high: false, modifier: None }
+    }
+
+    pub fn enc(&self) -> u8 {
+        self.num
+    }
+
+    pub fn from_enc(num: u8, size: usize) -> Self {
+        Self::new(num, size)
+    }
+
+    /// ah, ch, dh or bh for the numbers 4 to 7
+    pub fn high_byte(num: u8) -> Self {
+        Self { num, size: 8, high: true, modifier: None }
+    }
+
+    pub fn is_high_byte(&self) -> bool {
+        self.high
+    }
+
+    pub fn rax() -> Self { Self::new(0, 64) }
+    pub fn rcx() -> Self { Self::new(1, 64) }
+    pub fn rdx() -> Self { Self::new(2, 64) }
+    pub fn zero() -> Self { Self::new(0, 64) }
+    pub fn ra() -> Self { Self::new(1, 64) }
+}
+
+impl FromStr for Reg {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let find = |names: &[&str]| names.iter().position(|candidate| *candidate == name).map(|num| num as u8);
+
+        if let Some(num) = find(&X86_64) {
+            return Ok(Self::new(num, 64));
+        }
+
+        if let Some(num) = find(&X86_32) {
+            return Ok(Self::new(num, 32));
+        }
+
+        if let Some(num) = find(&RISCV) {
+            return Ok(Self::new(num, 64));
+        }
+
+        let numbered = |prefix: char| name.strip_prefix(prefix).and_then(|num| num.parse::<u8>().ok()).filter(|num| *num < 32);
+
+        match (numbered('x'), numbered('w')) {
+            (Some(num), _) => Ok(Self::new(num, 64)),
+            (_, Some(num)) => Ok(Self::new(num, 32)),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.size, self.num) {
+            (32, num @ 0..16) => f.write_str(X86_32[num as usize]),
+            (128, num) => write!(f, "xmm{}", num),
+            (_, num @ 0..16) => f.write_str(X86_64[num as usize]),
+            (_, num) => write!(f, "x{}", num),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Tmp(usize),
+    Reg(Reg),
+    Imm(i64),
+    Mem(MemoryDispl),
+}
+
+impl Operand {
+    /// `x2, lsl #2`
+    pub fn shifted(op: Operand, shift: Shift, amount: u8) -> Operand {
+        op.modified(Modifier::Shift(shift, amount))
+    }
+
+    /// `w2, sxtw #2`
+    pub fn extended(op: Operand, extend: Extend, amount: u8) -> Operand {
+        op.modified(Modifier::Extend(extend, amount))
+    }
+
+    fn modified(self, modifier: Modifier) -> Operand {
+        match self {
+            Operand::Reg(reg) => Operand::Reg(Reg { modifier: Some(modifier), ..reg }),
+            op => panic!("only registers are shifted or extended, not {:?}", op),
+        }
+    }
+
+    fn reg(&self) -> Reg {
+        match self {
+            Operand::Reg(reg) => *reg,
+            op => panic!("{:?} is no register", op),
+        }
+    }
+}
+
+impl From<DagOp> for Operand {
+    fn from(op: DagOp) -> Self {
+        match op {
+            DagOp::Gr(num) => Operand::Reg(Reg::new(num, 64)),
+            DagOp::Fp(num) => Operand::Reg(Reg::new(num, 128)),
+            DagOp::Imm(imm) => Operand::Imm(imm),
+            DagOp::Mem(base) => Operand::Mem(MemoryDispl::from_parts(Some(Reg::new(base, 64)), None, 1, 0)),
+            DagOp::Node(node) => panic!("{} was not folded", node),
+        }
+    }
+}
+
+pub enum MemoryOption {
+    Plus,
+    Minus,
+    Mul,
+}
+
+/// How an aarch64 memory operand writes the address back into its base
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indexing {
+    Offset,
+    /// `[x1, #8]!`
+    Pre,
+    /// `[x1], #8`
+    Post,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryDispl {
+    pub base: Option<Reg>,
+    pub index: Option<Reg>,
+    pub scale: u8,
+    pub displ: i32,
+    pub indexing: Indexing,
+}
+
+impl MemoryDispl {
+    pub fn from_parts(base: Option<Reg>, index: Option<Reg>, scale: u8, displ: i32) -> Self {
+        Self { base, index, scale, displ, indexing: Indexing::Offset }
+    }
+
+    pub fn pre_index(base: Operand, offset: i32) -> Operand {
+        Operand::Mem(Self { indexing: Indexing::Pre, ..Self::from_parts(Some(base.reg()), None, 1, offset) })
+    }
+
+    pub fn post_index(base: Operand, offset: i32) -> Operand {
+        Operand::Mem(Self { indexing: Indexing::Post, ..Self::from_parts(Some(base.reg()), None, 1, offset) })
+    }
+
+    pub fn base(&self) -> Option<Reg> {
+        self.base
+    }
+
+    pub fn index(&self) -> Option<Reg> {
+        self.index
+    }
+
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+
+    pub fn displ(&self) -> i32 {
+        self.displ
+    }
+}
+
+/// An instruction with the `Mnemonic` enum of the generated code
+#[derive(Debug, Clone, PartialEq)]
+pub struct Asm<M> {
+    pub mnemonic: M,
+    pub ops: Vec<Operand>,
+}
+
+impl<M: Copy> Asm<M> {
+    pub fn with0(mnemonic: M) -> Self {
+        Self { mnemonic, ops: Vec::new() }
+    }
+
+    pub fn with1(mnemonic: M, op1: Operand) -> Self {
+        Self { mnemonic, ops: vec![op1] }
+    }
+
+    pub fn with2(mnemonic: M, op1: Operand, op2: Operand) -> Self {
+        Self { mnemonic, ops: vec![op1, op2] }
+    }
+
+    pub fn with3(mnemonic: M, op1: Operand, op2: Operand, op3: Operand) -> Self {
+        Self { mnemonic, ops: vec![op1, op2, op3] }
+    }
+
+    pub fn mnemonic(&self) -> M {
+        self.mnemonic
+    }
+
+    pub fn operands(&self) -> &[Operand] {
+        &self.ops
+    }
+}
+
+/// Every operand is used as it is
+pub struct OperationHandler;
+
+impl OperationHandler {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn requires_new_const(&self, _: &DagOp) -> bool {
+        false
+    }
+
+    pub fn create_const(&self, _: &mut IR::Module) -> u32 {
+        0
+    }
+
+    /// nodes which were not folded have no operand
+    pub fn just_op(&self, op: &DagOp) -> bool {
+        !matches!(op, DagOp::Node(_))
+    }
+
+    pub fn compile_op(&self, op: &DagOp, _: Option<&u32>) -> Option<Operand> {
+        Some(op.clone().into())
+    }
+
+    pub fn inserts_instrs(&self, _: &DagOp) -> bool {
+        false
+    }
+
+    pub fn compile_instrs<M>(&self, _: &DagOp, _: Option<&u32>, _: DagTmpInfo) -> Option<Vec<Asm<M>>> {
+        None
+    }
+
+    pub fn tmp(&self, _: &DagOp, _: usize) -> Vec<DagTmpInfo> {
+        Vec::new()
+    }
+}
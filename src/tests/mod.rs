@@ -0,0 +1,42 @@
+//! Tests which compile the code generated from the .td files in this
+//! directory against a mock of the runtime
+
+mod mock;
+
+mod aarch64;
+mod bits;
+mod builder;
+mod commutative;
+mod config;
+mod encode;
+mod fallible;
+mod forms;
+mod header;
+mod implicit;
+mod info;
+mod inst;
+mod legality;
+mod map;
+mod parse;
+mod print;
+mod riscv;
+mod rust;
+mod select;
+mod source_map;
+mod tie;
+mod trace;
+mod tree;
+
+use crate::{ast, gen::CodeEmitter, AstTarget, Error};
+
+/// Generates the code of a .td source for the target
+fn generate(target: AstTarget, input: &str) -> Result<String, Error> {
+    let file = ast::parse(input)?;
+
+    CodeEmitter {
+        patterns: file.patterns,
+        insts: file.insts,
+        asm_parser: file.asm_parser,
+        config: file.config,
+    }.gen(target)
+}
@@ -0,0 +1,101 @@
+use super::mock::*;
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/parse.td");
+
+fn reg(num: u8) -> Operand {
+    Operand::Reg(Reg::new(num, 64))
+}
+
+fn mem(base: Option<u8>, index: Option<u8>, scale: u8, displ: i32) -> Operand {
+    Operand::Mem(MemoryDispl::from_parts(base.map(|num| Reg::new(num, 64)), index.map(|num| Reg::new(num, 64)), scale, displ))
+}
+
+fn parse(text: &str) -> Asm {
+    let mut asm = parse_asm(text).unwrap_or_else(|err| panic!("{}: {}", text, err));
+    assert_eq!(asm.len(), 1);
+    asm.remove(0)
+}
+
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RBX: u8 = 3;
+
+#[test]
+fn registers_and_immediates() {
+    assert_eq!(parse("add rax, rcx"), Asm::with2(Mnemonic::Add, reg(RAX), reg(RCX)));
+    assert_eq!(parse("add rax, -8"), Asm::with2(Mnemonic::Add, reg(RAX), Operand::Imm(-8)));
+    assert_eq!(parse("shl rax, 0x3f"), Asm::with2(Mnemonic::Shl, reg(RAX), Operand::Imm(63)));
+    assert_eq!(parse("ret"), Asm::with0(Mnemonic::Ret));
+}
+
+#[test]
+fn memory_operands() {
+    assert_eq!(parse("add rax, [rbx + 8]"), Asm::with2(Mnemonic::Add, reg(RAX), mem(Some(RBX), None, 1, 8)));
+    assert_eq!(parse("add qword ptr [rbx - 8], rcx"), Asm::with2(Mnemonic::Add, mem(Some(RBX), None, 1, -8), reg(RCX)));
+    assert_eq!(parse("lea rax, [rbx + rcx*4 + 0x100]"), Asm::with2(Mnemonic::Lea, reg(RAX), mem(Some(RBX), Some(RCX), 4, 0x100)));
+    assert_eq!(parse("lea rax, [8*rcx + 16]"), Asm::with2(Mnemonic::Lea, reg(RAX), mem(None, Some(RCX), 8, 16)));
+    assert_eq!(parse("lea rax, [rbx + rcx]"), Asm::with2(Mnemonic::Lea, reg(RAX), mem(Some(RBX), Some(RCX), 1, 0)));
+}
+
+#[test]
+fn sizes_are_checked() {
+    let mut bytes = Vec::new();
+
+    encode(&parse("and byte ptr [rbx], 1"), &mut bytes).unwrap();
+    encode(&parse("and [rbx], 1"), &mut bytes).unwrap();
+
+    assert_eq!(bytes, [0x80, 0x23, 0x01, 0x80, 0x23, 0x01]);
+    // the byte form takes the operands first, so the qword one can't be encoded
+    assert_eq!(parse_asm("and qword ptr [rbx], 1"), Err(AsmParseError::SizeMismatch(1, Mnemonic::And)));
+    assert_eq!(parse_asm("add byte ptr [rbx], rcx"), Err(AsmParseError::SizeMismatch(1, Mnemonic::Add)));
+    assert_eq!(parse_asm("add dword [rbx], rcx"), Err(AsmParseError::SizeMismatch(1, Mnemonic::Add)));
+    assert_eq!(parse("and qword ptr [rbx], 1000"), Asm::with2(Mnemonic::And, mem(Some(RBX), None, 1, 0), Operand::Imm(1000)));
+    assert_eq!(parse_asm("add near ptr [rbx], rcx"), Err(AsmParseError::InvalidOperand(1, "near ptr [rbx]".to_string())));
+}
+
+#[test]
+fn mnemonics_in_uppercase() {
+    assert_eq!(parse("ADD rax, rcx"), Asm::with2(Mnemonic::Add, reg(RAX), reg(RCX)));
+    assert_eq!(parse("Ret"), Asm::with0(Mnemonic::Ret));
+}
+
+#[test]
+fn lines_and_comments() {
+    let asm = parse_asm("
+        ; prologue
+        add rax, [rbx + rcx*4 + 8] # the element
+        ret
+    ");
+
+    assert_eq!(asm, Ok(vec![Asm::with2(Mnemonic::Add, reg(RAX), mem(Some(RBX), Some(RCX), 4, 8)), Asm::with0(Mnemonic::Ret)]));
+}
+
+#[test]
+fn parsed_asm_encodes() {
+    let mut bytes = Vec::new();
+
+    for asm in parse_asm("add rax, [rbx + rcx*4 + 8]\nret").unwrap() {
+        encode(&asm, &mut bytes).unwrap();
+    }
+
+    assert_eq!(bytes, [0x48, 0x03, 0x44, 0x8B, 0x08, 0xC3]);
+}
+
+#[test]
+fn errors_carry_the_line() {
+    assert_eq!(parse_asm("ret\nmul rax, rcx"), Err(AsmParseError::UnknownMnemonic(2, "mul".to_string())));
+    assert_eq!(parse_asm("add rax, [rbx + rcx*3]"), Err(AsmParseError::InvalidOperand(1, "[rbx + rcx*3]".to_string())));
+    assert_eq!(parse_asm("add rax, foo"), Err(AsmParseError::InvalidOperand(1, "foo".to_string())));
+    // the operands are checked against the forms
+    assert_eq!(parse_asm("lea rax, rcx"), Err(AsmParseError::NoForm(1, Mnemonic::Lea)));
+    assert_eq!(parse_asm("shl rax, -1"), Err(AsmParseError::NoForm(1, Mnemonic::Shl)));
+    assert_eq!(parse_asm("ret rax"), Err(AsmParseError::NoForm(1, Mnemonic::Ret)));
+}
+
+#[test]
+fn the_parser_needs_instructions() {
+    let err = super::generate(crate::AstTarget::X86, "config { asm_parser = parse_asm; }").unwrap_err();
+    assert_eq!(err.message, "`asm_parser` needs instructions declared with `def Inst`");
+}
@@ -0,0 +1,66 @@
+use super::mock::*;
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/print.td");
+
+fn reg(num: u8) -> Operand {
+    Operand::Reg(Reg::new(num, 64))
+}
+
+fn mem(base: Option<u8>, index: Option<u8>, scale: u8, displ: i32) -> Operand {
+    Operand::Mem(MemoryDispl::from_parts(base.map(|num| Reg::new(num, 64)), index.map(|num| Reg::new(num, 64)), scale, displ))
+}
+
+/// The asm in intel and AT&T syntax
+fn print(asm: Asm) -> (String, String) {
+    (print_asm(&asm, Syntax::Intel), print_asm(&asm, Syntax::Att))
+}
+
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RBX: u8 = 3;
+
+#[test]
+fn registers_and_immediates() {
+    assert_eq!(print(Asm::with2(Mnemonic::Add, reg(RAX), reg(RCX))), ("add rax, rcx".into(), "addq %rcx, %rax".into()));
+    assert_eq!(print(Asm::with2(Mnemonic::Add, reg(RAX), Operand::Imm(-8))), ("add rax, -8".into(), "addq $-8, %rax".into()));
+    assert_eq!(print(Asm::with0(Mnemonic::Ret)), ("ret".into(), "ret".into()));
+}
+
+#[test]
+fn memory_operands() {
+    assert_eq!(
+        print(Asm::with2(Mnemonic::Add, reg(RAX), mem(Some(RBX), Some(RCX), 4, 8))),
+        ("add rax, [rbx + rcx*4 + 8]".into(), "addq 8(%rbx,%rcx,4), %rax".into()),
+    );
+    assert_eq!(
+        print(Asm::with2(Mnemonic::Lea, reg(RAX), mem(None, Some(RCX), 8, -16))),
+        ("lea rax, [rcx*8 - 16]".into(), "leaq -16(,%rcx,8), %rax".into()),
+    );
+    assert_eq!(print(Asm::with2(Mnemonic::Lea, reg(RAX), mem(Some(RBX), None, 1, 0))), ("lea rax, [rbx]".into(), "leaq (%rbx), %rax".into()));
+}
+
+#[test]
+fn sizes_without_a_register_are_spelled_out() {
+    assert_eq!(
+        print(Asm::with2(Mnemonic::Add, mem(Some(RBX), None, 1, -8), Operand::Imm(1))),
+        ("add qword ptr [rbx - 8], 1".into(), "addq $1, -8(%rbx)".into()),
+    );
+    assert_eq!(print(Asm::with2(Mnemonic::And, mem(Some(RBX), None, 1, 0), Operand::Imm(1))), ("and byte ptr [rbx], 1".into(), "andb $1, (%rbx)".into()));
+}
+
+#[test]
+fn nasm_has_no_ptr() {
+    let asm = Asm::with2(Mnemonic::Add, mem(Some(RBX), Some(RCX), 4, 8), Operand::Imm(1));
+    assert_eq!(print_asm(&asm, Syntax::Nasm), "add qword [rbx + rcx*4 + 8], 1");
+
+    let asm = Asm::with2(Mnemonic::Add, reg(RAX), mem(Some(RBX), None, 1, -8));
+    assert_eq!(print_asm(&asm, Syntax::Nasm), "add rax, [rbx - 8]");
+}
+
+#[test]
+fn vector_registers_have_no_suffix() {
+    let xmm = |num| Operand::Reg(Reg::new(num, 128));
+    assert_eq!(print(Asm::with2(Mnemonic::Movsd, xmm(0), mem(Some(RBX), None, 1, 0))), ("movsd xmm0, [rbx]".into(), "movsd (%rbx), %xmm0".into()));
+}
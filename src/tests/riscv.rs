@@ -0,0 +1,259 @@
+use crate::AstTarget;
+
+use super::{generate, mock::{dag::{DagNode, DagOp}, IR::{self, TypeMetadata}, *}};
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/riscv.td", riscv64);
+
+/// The code generated for an asm line of a load, without whitespace and the
+/// trailing commas of wrapped calls
+fn line(asm: &str) -> Result<String, String> {
+    let code = generate(AstTarget::RiscV, &format!("def Pat<Load gr -> gr> i64 {{ asm -> {}; }}", asm)).map_err(|err| err.message)?;
+    Ok(code.replace(char::is_whitespace, "").replace(",)", ")"))
+}
+
+fn assert_builds(asm: &str, build: &str) {
+    let code = line(asm).unwrap();
+    let build = build.replace(char::is_whitespace, "");
+    assert!(code.contains(&build), "`{}` does not build `{}`", asm, build);
+}
+
+#[test]
+fn memory_operands() {
+    assert_builds("ld $out, ($1)", "Asm::with2(Mnemonic::Ld, node.get_out().into(), MemoryDispl::new(ls))");
+    assert_builds("ld $out, 8($1)", "MemoryDispl::new(ls, MemoryOption::Plus, 8)");
+    assert_builds("ld $out, -8($1)", "MemoryDispl::new(ls, MemoryOption::Minus, 8)");
+    assert_builds("ld $out, %lo(sym)($1)", "MemoryDispl::new(ls, MemoryOption::Plus, Operand::lo(sym))");
+}
+
+#[test]
+fn relocations() {
+    assert_builds("lui $out, %hi(sym)", "Asm::with2(Mnemonic::Lui, node.get_out().into(), Operand::hi(sym))");
+    assert_builds("auipc $out, %pcrel_hi(sym)", "Operand::pcrel_hi(sym)");
+
+    assert_eq!(line("lui $out, %high(sym)"), Err("unknown relocation `%high`, expected one of %hi, %lo, %pcrel_hi, %pcrel_lo".to_string()));
+}
+
+#[test]
+fn pseudo_instructions() {
+    assert_builds("mv $out, $1", "Asm::with3(Mnemonic::Addi, node.get_out().into(), ls, Operand::Imm(0))");
+    assert_builds("not $out, $1", "Asm::with3(Mnemonic::Xori, node.get_out().into(), ls, Operand::Imm(-1))");
+    assert_builds("j 16", "Asm::with2(Mnemonic::Jal, Operand::Reg(Reg::zero()), 16)");
+    assert_builds("ret", "Asm::with3(Mnemonic::Jalr, Operand::Reg(Reg::zero()), Operand::Reg(Reg::ra()), Operand::Imm(0))");
+    assert_builds("nop", "Asm::with3(Mnemonic::Addi, Operand::Reg(Reg::zero()), Operand::Reg(Reg::zero()), Operand::Imm(0))");
+
+    assert_builds("li $out, 42", "Asm::with3(Mnemonic::Addi, node.get_out().into(), Operand::Reg(Reg::zero()), Operand::Imm(42))");
+    assert_builds("li $out, 0x12345", "
+        asm.push(Asm::with2(Mnemonic::Lui, node.get_out().into(), Operand::Imm(18)));
+        asm.push(Asm::with3(Mnemonic::Addiw, node.get_out().into(), node.get_out().into(), Operand::Imm(837)));
+    ");
+    assert_builds("li $out, $1", "asm.extend(expand_li(node.get_out().into(), ls));");
+
+    assert_eq!(line("mv $out"), Err("`mv` takes 2 operands".to_string()));
+}
+
+#[test]
+fn pseudo_instructions_need_their_expansion() {
+    let err = generate(AstTarget::RiscV, "def Inst addi : (r, r, imm12); def Pat<Copy imm -> gr> i64 { asm -> li $out, $1; }").unwrap_err();
+    assert_eq!(err.message, "`li` of an operand expands to addi, lui, addiw, slli, which are not all declared with `def Inst`");
+
+    // declared pseudo instructions are kept
+    let code = generate(AstTarget::RiscV, "def Inst mv : (r, r); def Pat<Copy gr -> gr> i64 { asm -> mv $out, $1; }").unwrap();
+    assert!(code.contains("Mnemonic::Mv"));
+}
+
+/// Runs the instructions of `li` and returns the loaded value
+fn load(value: i64) -> i64 {
+    let mut asm = Vec::new();
+    compile(&mut asm, DagNode::new(DagOpCode::Copy, TypeMetadata::i64, vec![DagOp::Imm(value)], DagOp::Gr(10)), &mut IR::Module);
+
+    let mut rd = 0i64;
+
+    for asm in &asm {
+        let imm = match asm.operands().last() {
+            Some(Operand::Imm(imm)) => *imm,
+            _ => panic!("{:?} has no immediate", asm),
+        };
+
+        rd = match asm.mnemonic() {
+            Mnemonic::Addi if asm.operands()[1] == Operand::Reg(Reg::zero()) => imm,
+            Mnemonic::Addi => rd.wrapping_add(imm),
+            Mnemonic::Lui => imm << 12,
+            Mnemonic::Addiw => rd.wrapping_add(imm) as i32 as i64,
+            Mnemonic::Slli => rd << imm,
+            mnemonic => panic!("`li` used {}", mnemonic),
+        };
+
+        let mut bytes = Vec::new();
+        encode(asm, &mut bytes).unwrap_or_else(|err| panic!("{:?}: {}", asm, err));
+    }
+
+    rd
+}
+
+const LI_VALUES: [i64; 12] = [0, -1, 2047, -2048, 2048, 0x12345, 0x7fff_ffff, -0x8000_0000, 0x1_0000_0000, 0x1234_5678_9abc_def0, i64::MAX, i64::MIN];
+
+#[test]
+fn load_immediates() {
+    for value in LI_VALUES {
+        assert_eq!(load(value), value, "{:#x}", value);
+    }
+}
+
+/// The mnemonics and immediates `li` of a literal expands to
+fn li_at_compile_time(value: i64) -> Vec<(String, i64)> {
+    let code = generate(AstTarget::RiscV, &format!("def Pat<Copy gr -> gr> i64 {{ asm -> li $out, {}; }}", value)).unwrap();
+    let code = code.replace(char::is_whitespace, "");
+
+    code.split("asm.push(").skip(1)
+        .map(|push| {
+            let mnemonic = push.split("Mnemonic::").nth(1).unwrap().split(',').next().unwrap();
+            let imm = push.split("Operand::Imm(").nth(1).unwrap().split(')').next().unwrap();
+            (mnemonic.to_string(), imm.parse().unwrap())
+        })
+        .collect()
+}
+
+/// The mnemonics and immediates `expand_li` loads the value with
+fn li_at_runtime(value: i64) -> Vec<(String, i64)> {
+    let mut asm = Vec::new();
+    compile(&mut asm, DagNode::new(DagOpCode::Copy, TypeMetadata::i64, vec![DagOp::Imm(value)], DagOp::Gr(10)), &mut IR::Module);
+
+    asm.iter()
+        .map(|asm| match asm.operands().last() {
+            Some(Operand::Imm(imm)) => (format!("{:?}", asm.mnemonic()), *imm),
+            _ => panic!("{:?} has no immediate", asm),
+        })
+        .collect()
+}
+
+#[test]
+fn literals_load_like_runtime_values() {
+    for value in LI_VALUES {
+        assert_eq!(li_at_compile_time(value), li_at_runtime(value), "{:#x}", value);
+    }
+}
+
+fn reg(num: u8) -> Operand {
+    Operand::Reg(Reg::new(num, 64))
+}
+
+fn mem(base: u8, displ: i32) -> Operand {
+    Operand::Mem(MemoryDispl::from_parts(Some(Reg::new(base, 64)), None, 1, displ))
+}
+
+const RA: u8 = 1;
+const SP: u8 = 2;
+const A0: u8 = 10;
+const A1: u8 = 11;
+
+#[test]
+fn parser() {
+    let asm = parse_asm("
+        add a0, a0, a1
+        addi a0, a1, -2048 # the smallest immediate
+        ld ra, 8(sp)
+        sd ra, (sp)
+        fence
+        fence 3, 3
+        ebreak
+    ");
+
+    assert_eq!(asm, Ok(vec![
+        Asm::with3(Mnemonic::Add, reg(A0), reg(A0), reg(A1)),
+        Asm::with3(Mnemonic::Addi, reg(A0), reg(A1), Operand::Imm(-2048)),
+        Asm::with2(Mnemonic::Ld, reg(RA), mem(SP, 8)),
+        Asm::with2(Mnemonic::Sd, reg(RA), mem(SP, 0)),
+        Asm::with0(Mnemonic::Fence),
+        Asm::with2(Mnemonic::Fence, Operand::Imm(3), Operand::Imm(3)),
+        Asm::with0(Mnemonic::Ebreak),
+    ]));
+
+    let mut bytes = Vec::new();
+    for asm in parse_asm("fence\nfence 3, 3").unwrap() {
+        encode(&asm, &mut bytes).unwrap();
+    }
+    assert_eq!(bytes, [0x0F, 0x00, 0xF0, 0x0F, 0x0F, 0x00, 0x30, 0x03]);
+
+    assert_eq!(parse_asm("addi a0, a1, 2048"), Err(AsmParseError::NoForm(1, Mnemonic::Addi)));
+    assert_eq!(parse_asm("ld ra, %lo(sym)(sp)"), Err(AsmParseError::InvalidOperand(1, "%lo(sym)(sp)".to_string())));
+}
+
+/// Compiles a node of the type into a0 and encodes it with the temporaries in t0, t1, ...
+fn compiled(opcode: DagOpCode, ty: TypeMetadata, ops: Vec<DagOp>) -> Vec<Asm> {
+    let mut asm = Vec::new();
+    compile(&mut asm, DagNode::new(opcode, ty, ops, DagOp::Gr(A0)), &mut IR::Module);
+
+    for asm in &mut asm {
+        for op in &mut asm.ops {
+            if let Operand::Tmp(num) = op {
+                *op = reg(T0 + *num as u8);
+            }
+        }
+
+        encode(asm, &mut Vec::new()).unwrap_or_else(|err| panic!("{:?}: {}", asm, err));
+    }
+
+    asm
+}
+
+const T0: u8 = 5;
+
+#[test]
+fn immediates_which_do_not_fit_are_loaded() {
+    assert_eq!(compiled(DagOpCode::Add, TypeMetadata::i64, vec![DagOp::Gr(A1), DagOp::Imm(5000)]), [
+        Asm::with2(Mnemonic::Lui, reg(T0), Operand::Imm(1)),
+        Asm::with3(Mnemonic::Addiw, reg(T0), reg(T0), Operand::Imm(904)),
+        Asm::with3(Mnemonic::Add, reg(A0), reg(A1), reg(T0)),
+    ]);
+
+    assert_eq!(compiled(DagOpCode::And, TypeMetadata::i64, vec![DagOp::Gr(A1), DagOp::Imm(0xff)]), [
+        Asm::with3(Mnemonic::Addi, reg(T0), reg(0), Operand::Imm(0xff)),
+        Asm::with3(Mnemonic::And, reg(A0), reg(A1), reg(T0)),
+    ]);
+}
+
+#[test]
+fn shifts() {
+    assert_eq!(compiled(DagOpCode::Shl, TypeMetadata::i64, vec![DagOp::Gr(A1), DagOp::Imm(65)]), [Asm::with3(Mnemonic::Slli, reg(A0), reg(A1), Operand::Imm(1))]);
+    assert_eq!(compiled(DagOpCode::Shr, TypeMetadata::i64, vec![DagOp::Gr(A1), DagOp::Gr(A0)]), [Asm::with3(Mnemonic::Sra, reg(A0), reg(A1), reg(A0))]);
+    assert_eq!(compiled(DagOpCode::Shr, TypeMetadata::u64, vec![DagOp::Gr(A1), DagOp::Imm(63)]), [Asm::with3(Mnemonic::Srli, reg(A0), reg(A1), Operand::Imm(63))]);
+    assert_eq!(compiled(DagOpCode::Shr, TypeMetadata::i32, vec![DagOp::Gr(A1), DagOp::Imm(33)]), [Asm::with3(Mnemonic::Sraiw, reg(A0), reg(A1), Operand::Imm(1))]);
+    assert_eq!(compiled(DagOpCode::Shr, TypeMetadata::u32, vec![DagOp::Gr(A1), DagOp::Gr(A0)]), [Asm::with3(Mnemonic::Srlw, reg(A0), reg(A1), reg(A0))]);
+}
+
+#[test]
+fn compares_and_branches() {
+    assert_eq!(compiled(DagOpCode::CmpEq, TypeMetadata::i64, vec![DagOp::Gr(A0), DagOp::Gr(A1)]), [
+        Asm::with3(Mnemonic::Xor, reg(T0), reg(A0), reg(A1)),
+        Asm::with3(Mnemonic::Sltiu, reg(A0), reg(T0), Operand::Imm(1)),
+    ]);
+    assert_eq!(compiled(DagOpCode::CmpNe, TypeMetadata::u32, vec![DagOp::Gr(A0), DagOp::Gr(A1)]), [
+        Asm::with3(Mnemonic::Xor, reg(T0), reg(A0), reg(A1)),
+        Asm::with3(Mnemonic::Sltu, reg(A0), reg(0), reg(T0)),
+    ]);
+    assert_eq!(compiled(DagOpCode::CmpLt, TypeMetadata::i32, vec![DagOp::Gr(A0), DagOp::Gr(A1)]), [Asm::with3(Mnemonic::Slt, reg(A0), reg(A0), reg(A1))]);
+    assert_eq!(compiled(DagOpCode::CmpLt, TypeMetadata::ptr, vec![DagOp::Gr(A0), DagOp::Gr(A1)]), [Asm::with3(Mnemonic::Sltu, reg(A0), reg(A0), reg(A1))]);
+
+    assert_eq!(compiled(DagOpCode::Br, TypeMetadata::i64, vec![DagOp::Imm(-8)]), [Asm::with2(Mnemonic::Jal, reg(0), Operand::Imm(-8))]);
+    assert_eq!(compiled(DagOpCode::BrEq, TypeMetadata::i64, vec![DagOp::Gr(A0), DagOp::Gr(A1), DagOp::Imm(16)]), [Asm::with3(Mnemonic::Beq, reg(A0), reg(A1), Operand::Imm(16))]);
+    assert_eq!(compiled(DagOpCode::BrNe, TypeMetadata::u8, vec![DagOp::Gr(A0), DagOp::Gr(A1), DagOp::Imm(16)]), [Asm::with3(Mnemonic::Bne, reg(A0), reg(A1), Operand::Imm(16))]);
+    assert_eq!(compiled(DagOpCode::BrLt, TypeMetadata::u64, vec![DagOp::Gr(A0), DagOp::Gr(A1), DagOp::Imm(16)]), [Asm::with3(Mnemonic::Bltu, reg(A0), reg(A1), Operand::Imm(16))]);
+    assert_eq!(compiled(DagOpCode::BrGe, TypeMetadata::i16, vec![DagOp::Gr(A0), DagOp::Gr(A1), DagOp::Imm(16)]), [Asm::with3(Mnemonic::Bge, reg(A0), reg(A1), Operand::Imm(16))]);
+}
+
+#[test]
+fn loads_and_stores() {
+    let load = |ty| compiled(DagOpCode::Load, ty, vec![DagOp::Mem(SP)]);
+    let store = |ty| compiled(DagOpCode::Store, ty, vec![DagOp::Mem(SP), DagOp::Gr(A1)]);
+
+    assert_eq!(load(TypeMetadata::i8), [Asm::with2(Mnemonic::Lb, reg(A0), mem(SP, 0))]);
+    assert_eq!(load(TypeMetadata::u16), [Asm::with2(Mnemonic::Lhu, reg(A0), mem(SP, 0))]);
+    assert_eq!(load(TypeMetadata::u32), [Asm::with2(Mnemonic::Lwu, reg(A0), mem(SP, 0))]);
+    assert_eq!(load(TypeMetadata::ptr), [Asm::with2(Mnemonic::Ld, reg(A0), mem(SP, 0))]);
+
+    assert_eq!(store(TypeMetadata::u8), [Asm::with2(Mnemonic::Sb, reg(A1), mem(SP, 0))]);
+    assert_eq!(store(TypeMetadata::i32), [Asm::with2(Mnemonic::Sw, reg(A1), mem(SP, 0))]);
+    assert_eq!(store(TypeMetadata::u64), [Asm::with2(Mnemonic::Sd, reg(A1), mem(SP, 0))]);
+}
@@ -0,0 +1,30 @@
+use crate::{AstTarget, Builder};
+
+fn lines(code: &str, start: &str) -> Vec<String> {
+    code.lines().map(str::trim).filter(|line| line.starts_with(start)).map(String::from).collect()
+}
+
+#[test]
+fn syntax_errors_point_into_the_td_file() {
+    let err = Builder::new().input("src/tests/bad.td").generate_code().unwrap_err();
+
+    assert_eq!(err.to_string(), "src/tests/bad.td:4:21: expected an expression (in `def Pat<Add gr, gr -> gr> i64`)");
+}
+
+#[test]
+fn variables_are_whole_tokens() {
+    let input = "def Pat<Add gr, gr -> gr> i64 {\n    rust -> let y = $1; let z = $10;\n    asm -> add $out, $2;\n}\n";
+    let err = super::generate(AstTarget::X86, input).unwrap_err();
+
+    assert_eq!(err.message, "unknown node variable `$10`");
+    assert_eq!(err.loc.map(|loc| (loc.line, loc.col)), Some((2, 33)));
+}
+
+#[test]
+fn string_literals_are_not_substituted() {
+    let input = "def Pat<Add gr, gr -> gr> i64 map %t1, gr; {\n    rust -> let s = \"%t1 $1\"; let y = %t1;\n    asm -> add $out, $2;\n}\n";
+    let code = super::generate(AstTarget::X86, input).unwrap();
+
+    assert_eq!(lines(&code, "let s"), ["let s = \"%t1 $1\";"]);
+    assert_eq!(lines(&code, "let y"), ["let y = Operand::Tmp(1);"]);
+}
@@ -0,0 +1,63 @@
+use super::mock::{dag::{DagNode, DagOp}, IR::{self, TypeMetadata}, *};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Mov, Add, Add3, Addc, Sub,
+}
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/select.td");
+
+fn add(ty: TypeMetadata, rs: DagOp) -> DagNode {
+    DagNode::new(DagOpCode::Add, ty, vec![DagOp::Gr(1), rs], DagOp::Gr(0))
+}
+
+fn mnemonics(node: DagNode, goal: CostGoal) -> Vec<Mnemonic> {
+    let mut asm = Vec::new();
+    compile_with(&mut asm, node, &mut IR::Module, goal);
+    asm.iter().map(Asm::mnemonic).collect()
+}
+
+#[test]
+fn cheapest_pattern_wins() {
+    let node = add(TypeMetadata::i64, DagOp::Gr(2));
+
+    assert_eq!(select_with(&node, CostGoal::Instrs), Some(1));
+    assert_eq!(select_with(&node, CostGoal::Latency), Some(0));
+    assert_eq!(select_with(&node, CostGoal::Size), Some(2));
+
+    assert_eq!(mnemonics(node.clone(), CostGoal::Instrs), [Mnemonic::Add3]);
+    assert_eq!(mnemonics(node.clone(), CostGoal::Latency), [Mnemonic::Mov, Mnemonic::Add]);
+    assert_eq!(mnemonics(node, CostGoal::Size), [Mnemonic::Addc]);
+}
+
+#[test]
+fn ties_keep_the_first_pattern() {
+    // the second and third pattern both need one instruction
+    assert_eq!(PATTERNS[1].cost.instrs, PATTERNS[2].cost.instrs);
+    assert_eq!(select(&add(TypeMetadata::i64, DagOp::Gr(2))), Some(1));
+}
+
+#[test]
+fn queries_follow_the_goal() {
+    let node = add(TypeMetadata::i64, DagOp::Gr(2));
+
+    assert_eq!(overwrittes(&node), []);
+    assert_eq!(overwrittes_with(&node, CostGoal::Size), [Reg::rcx()]);
+
+    let mut asm = Vec::new();
+    compile(&mut asm, node.clone(), &mut IR::Module);
+    assert_eq!(asm, [Asm::with3(Mnemonic::Add3, Operand::Reg(Reg::new(0, 64)), Operand::Reg(Reg::new(1, 64)), Operand::Reg(Reg::new(2, 64)))]);
+}
+
+#[test]
+fn without_costs_the_first_match_wins() {
+    let node = DagNode::new(DagOpCode::Sub, TypeMetadata::i64, vec![DagOp::Gr(1), DagOp::Imm(5)], DagOp::Gr(0));
+    assert_eq!(select_with(&node, CostGoal::Size), Some(3));
+
+    let node = DagNode::new(DagOpCode::Sub, TypeMetadata::i64, vec![DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(0));
+    assert_eq!(select(&node), Some(4));
+
+    assert_eq!(select(&add(TypeMetadata::i32, DagOp::Gr(2))), None);
+}
@@ -0,0 +1,63 @@
+use crate::{gen, AstTarget};
+
+const INPUT: &str = "config { module = IR::Module; }
+
+def Pat<Add gr, gr -> gr> i64 overwrite rcx; {
+    asm -> add $out, $2;
+}
+
+def Pat<Sub gr, imm -> gr> i64 cost instrs 2; {
+    asm -> mov $out, $1;
+    asm -> sub $out, $2;
+}
+
+def Pat<Sub gr, gr -> gr> i64 cost instrs 1; {
+    asm -> sub $out, $2;
+}
+";
+
+/// The `(start, end, line)` of every mapping
+fn mappings(map: &str) -> Vec<(usize, usize, usize)> {
+    let field = |line: &str, name: &str| {
+        let value = line.split(&format!("\"{}\": ", name)).nth(1).unwrap();
+        value[..value.find([',', ' ']).unwrap()].parse::<usize>().unwrap()
+    };
+
+    map.lines().filter(|line| line.contains("\"start\"")).map(|line| (field(line, "start"), field(line, "end"), field(line, "line"))).collect()
+}
+
+#[test]
+fn arms_are_marked_with_their_pattern() {
+    let code = super::generate(AstTarget::X86, INPUT).unwrap();
+
+    let markers = code.lines().filter_map(|line| line.trim().strip_prefix("// ytbgen: ")).collect::<Vec<_>>();
+
+    // the checks of `select_with`, the arms of `compile_*`, `tmps_with` and `overwrittes_with`
+    assert_eq!(markers, [
+        "<input>:3", "<input>:7", "<input>:12",
+        "<input>:3", "<input>:7", "<input>:12",
+        "<input>:3", "<input>:7", "<input>:12",
+        "<input>:3",
+    ]);
+    assert!(!code.contains("#[ytbgen"));
+}
+
+#[test]
+fn ranges_cover_the_marked_items() {
+    let code = super::generate(AstTarget::X86, INPUT).unwrap();
+    let lines = code.lines().collect::<Vec<_>>();
+    let mappings = mappings(&gen::source_map(&code));
+
+    let markers = lines.iter().filter(|line| line.trim_start().starts_with("// ytbgen: ")).count();
+    assert_eq!(mappings.len(), markers);
+
+    for (start, end, line) in mappings {
+        assert_eq!(lines[start - 2].trim(), format!("// ytbgen: <input>:{}", line));
+
+        // the range is balanced and ends with the closing brace of the item
+        let item = lines[start - 1..end].join("\n");
+        assert_eq!(item.matches('{').count(), item.matches('}').count(), "{}", item);
+        assert!(item.trim_end().ends_with(['}', ',']), "{}", item);
+        assert!(syn::parse_str::<syn::Arm>(&item).is_ok() || syn::parse_str::<syn::Stmt>(&item).is_ok(), "{}", item);
+    }
+}
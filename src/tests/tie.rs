@@ -0,0 +1,73 @@
+use super::mock::{dag::{DagNode, DagOp}, IR::{self, StdTypeMetadata, TypeMetadata, VecTy}, *};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Mov, Movss, Movsd, Movaps, Sub, Subss, Mulsd, Divps, Add,
+}
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/tie.td");
+
+fn compiled(opcode: DagOpCode, ty: TypeMetadata, ops: [DagOp; 2], out: DagOp) -> Vec<Asm> {
+    let mut asm = Vec::new();
+    compile(&mut asm, DagNode::new(opcode, ty, ops.to_vec(), out), &mut IR::Module);
+    asm
+}
+
+fn reg(num: u8) -> Operand {
+    Operand::Reg(Reg::new(num, 64))
+}
+
+#[test]
+fn copies_only_if_out_differs() {
+    assert_eq!(compiled(DagOpCode::Sub, TypeMetadata::i64, [DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(0)), [
+        Asm::with2(Mnemonic::Mov, reg(0), reg(1)),
+        Asm::with2(Mnemonic::Sub, reg(0), reg(2)),
+    ]);
+
+    assert_eq!(compiled(DagOpCode::Sub, TypeMetadata::i64, [DagOp::Gr(0), DagOp::Gr(2)], DagOp::Gr(0)), [
+        Asm::with2(Mnemonic::Sub, reg(0), reg(2)),
+    ]);
+}
+
+#[test]
+fn saves_the_other_operand_if_it_is_out() {
+    assert_eq!(compiled(DagOpCode::Sub, TypeMetadata::i64, [DagOp::Gr(1), DagOp::Gr(0)], DagOp::Gr(0)), [
+        Asm::with2(Mnemonic::Mov, Operand::Tmp(TIE_TMP), reg(0)),
+        Asm::with2(Mnemonic::Mov, reg(0), reg(1)),
+        Asm::with2(Mnemonic::Sub, reg(0), Operand::Tmp(TIE_TMP)),
+    ]);
+
+    let node = DagNode::new(DagOpCode::Sub, TypeMetadata::i64, vec![DagOp::Gr(1), DagOp::Gr(0)], DagOp::Gr(0));
+    assert!(tmps(&node).iter().any(|tmp| tmp.num == TIE_TMP && tmp.class == "gr" && tmp.size == TypeMetadata::i64));
+}
+
+#[test]
+fn commutative_patterns_swap_instead() {
+    assert_eq!(compiled(DagOpCode::Add, TypeMetadata::i64, [DagOp::Gr(1), DagOp::Gr(0)], DagOp::Gr(0)), [
+        Asm::with2(Mnemonic::Add, reg(0), reg(1)),
+    ]);
+}
+
+#[test]
+fn floats_are_moved_by_type() {
+    let fp = |num| Operand::Reg(Reg::new(num, 128));
+
+    assert_eq!(compiled(DagOpCode::Sub, TypeMetadata::f32, [DagOp::Fp(1), DagOp::Fp(0)], DagOp::Fp(0)), [
+        Asm::with2(Mnemonic::Movss, Operand::Tmp(TIE_TMP), fp(0)),
+        Asm::with2(Mnemonic::Movss, fp(0), fp(1)),
+        Asm::with2(Mnemonic::Subss, fp(0), Operand::Tmp(TIE_TMP)),
+    ]);
+
+    let node = DagNode::new(DagOpCode::Sub, TypeMetadata::f32, vec![DagOp::Fp(1), DagOp::Fp(0)], DagOp::Fp(0));
+    assert!(tmps(&node).iter().any(|tmp| tmp.num == TIE_TMP && tmp.class == "fp" && tmp.size == TypeMetadata::f32));
+    assert!(PATTERNS[1].tmps.iter().any(|tmp| tmp.num == TIE_TMP && tmp.kind == OperandKind::Fp));
+
+    // the type of `float` patterns is looked up at runtime
+    assert_eq!(compiled(DagOpCode::Mul, TypeMetadata::f64, [DagOp::Fp(1), DagOp::Fp(2)], DagOp::Fp(0))[0], Asm::with2(Mnemonic::Movsd, fp(0), fp(1)));
+    assert_eq!(compiled(DagOpCode::Mul, TypeMetadata::f32, [DagOp::Fp(1), DagOp::Fp(2)], DagOp::Fp(0))[0], Asm::with2(Mnemonic::Movss, fp(0), fp(1)));
+
+    let vector = TypeMetadata::Vector(VecTy { size: 4, ty: StdTypeMetadata::f32 });
+    assert_eq!(compiled(DagOpCode::Div, vector, [DagOp::Fp(1), DagOp::Fp(2)], DagOp::Fp(0))[0], Asm::with2(Mnemonic::Movaps, fp(0), fp(1)));
+}
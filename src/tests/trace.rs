@@ -0,0 +1,53 @@
+use std::cell::RefCell;
+
+use super::mock::{dag::{DagNode, DagOp}, IR::{self, TypeMetadata}, *};
+use crate::AstTarget;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Add, Sub,
+}
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/trace.td");
+
+thread_local! {
+    static TRACE: RefCell<Vec<(PatternId, String, DagNode)>> = const { RefCell::new(Vec::new()) };
+}
+
+fn record(id: PatternId, loc: &str, node: &DagNode) {
+    TRACE.with(|trace| trace.borrow_mut().push((id, loc.to_string(), node.clone())));
+}
+
+#[test]
+fn hook_receives_every_compiled_node() {
+    let add = DagNode::new(DagOpCode::Add, TypeMetadata::i64, vec![DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(0));
+    let sub = DagNode::new(DagOpCode::Sub, TypeMetadata::i64, vec![DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(0));
+
+    let mut asm: Vec<Asm> = Vec::new();
+    compile(&mut asm, add.clone(), &mut IR::Module);
+    compile(&mut asm, sub.clone(), &mut IR::Module);
+
+    let trace = TRACE.take();
+    assert_eq!(trace.iter().map(|(id, _, node)| (*id, node.clone())).collect::<Vec<_>>(), [(0, add), (1, sub)]);
+    assert!(trace[0].1.replace('\\', "/").ends_with("src/tests/trace.td:8"), "{}", trace[0].1);
+    assert!(trace[1].1.replace('\\', "/").ends_with("src/tests/trace.td:12"), "{}", trace[1].1);
+}
+
+#[test]
+fn tracing_is_behind_the_feature() {
+    let input = "config { trace = record; }\ndef Pat<Add gr, gr -> gr> i64 {\n    asm -> add $out, $2;\n}\n";
+    let code = super::generate(AstTarget::X86, input).unwrap();
+
+    assert!(code.contains("#[cfg(feature = \"trace\")] record(0, \"<input>:2\", &node);"), "{}", code);
+}
+
+#[test]
+fn no_trace_code_without_a_hook() {
+    let input = "config { trace_feature = none; }\ndef Pat<Add gr, gr -> gr> i64 {\n    asm -> add $out, $2;\n}\n";
+    let code = super::generate(AstTarget::X86, input).unwrap();
+
+    assert!(!code.contains("cfg(feature"), "{}", code);
+    assert!(!code.contains("trace"), "{}", code);
+}
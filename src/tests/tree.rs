@@ -0,0 +1,86 @@
+use crate::AstTarget;
+
+use super::{generate, mock::{dag::{DagNode, DagOp}, IR::{self, TypeMetadata}, *}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Mov, Add, Imul,
+}
+
+type Asm = super::mock::Asm<Mnemonic>;
+
+ytbgen_macros::include_patterns!("src/tests/tree.td");
+
+fn reg(num: u8) -> Operand {
+    Operand::Reg(Reg::new(num, 64))
+}
+
+fn node(opcode: DagOpCode, ops: Vec<DagOp>, out: DagOp) -> DagNode {
+    DagNode::new(opcode, TypeMetadata::i64, ops, out)
+}
+
+/// `$1 + $2 * scale` into gr 0
+fn scaled_add(scale: i64) -> DagNode {
+    let mul = node(DagOpCode::Mul, vec![DagOp::Gr(2), DagOp::Imm(scale)], DagOp::Gr(3));
+    node(DagOpCode::Add, vec![DagOp::Gr(1), DagOp::Node(Box::new(mul))], DagOp::Gr(0))
+}
+
+fn compiled(node: DagNode) -> Vec<Asm> {
+    let mut asm = Vec::new();
+    compile(&mut asm, node, &mut IR::Module);
+    asm
+}
+
+#[test]
+fn nested_nodes_are_matched() {
+    assert_eq!(select(&scaled_add(4)), Some(0));
+    assert_eq!(select(&scaled_add(8)), Some(0));
+
+    assert_eq!(compiled(scaled_add(8)), [
+        Asm::with3(Mnemonic::Imul, reg(0), reg(2), Operand::Imm(8)),
+        Asm::with2(Mnemonic::Add, reg(0), reg(1)),
+    ]);
+}
+
+#[test]
+fn other_constants_fall_through() {
+    // the folded node is no gr either, so no pattern matches
+    assert_eq!(select(&scaled_add(3)), None);
+
+    let flat = node(DagOpCode::Add, vec![DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(0));
+    assert_eq!(select(&flat), Some(2));
+}
+
+#[test]
+fn operands_of_nested_nodes_are_bound() {
+    let mul = node(DagOpCode::Mul, vec![DagOp::Gr(1), DagOp::Gr(2)], DagOp::Gr(3));
+    let add = node(DagOpCode::Add, vec![DagOp::Node(Box::new(mul)), DagOp::Imm(16)], DagOp::Gr(0));
+
+    assert_eq!(select(&add), Some(1));
+    assert_eq!(compiled(add), [
+        Asm::with2(Mnemonic::Mov, reg(0), reg(1)),
+        Asm::with2(Mnemonic::Imul, reg(0), reg(2)),
+        Asm::with2(Mnemonic::Add, reg(0), Operand::Imm(16)),
+    ]);
+}
+
+/// The code generated for a scaled `lea`, without whitespace and the trailing
+/// commas of wrapped calls
+fn scaled_lea() -> String {
+    let code = generate(AstTarget::X86, "def Pat<Add(gr, Mul(gr, 4|8)) -> gr> i64 { asm -> lea $out, [$1 + $2.1 * $2.2]; }").unwrap();
+    code.replace(char::is_whitespace, "").replace(",)", ")")
+}
+
+#[test]
+fn nested_temporaries_have_the_type_of_their_node() {
+    let code = scaled_lea();
+
+    assert!(code.contains("dag::DagTmpInfo::new(0xF0,node.get_ty())"));
+    assert!(code.contains("dag::DagTmpInfo::new(0xF3,node.get_op_node(1).unwrap().get_ty())"));
+    assert!(code.contains("dag::DagTmpInfo::new(0xF4,node.get_op_node(1).unwrap().get_ty())"));
+}
+
+#[test]
+fn scaled_indices() {
+    assert!(scaled_lea().contains("Asm::with2(Mnemonic::Lea,node.get_out().into(),MemoryDispl::new(ls,MemoryOption::Plus,op_2_1,MemoryOption::Mul,op_2_2))"));
+}